@@ -13,10 +13,15 @@ pub struct Command {
 #[derive(clap::Subcommand, Clone, Debug)]
 pub enum Subcommand {
     Add(AddCommand),
+    Adopt(AdoptCommand),
+    Backups(BackupsCommand),
     Create(CreateCommand),
+    Drift(DriftCommand),
     Init(InitCommand),
     Merge(MergeCommand),
+    Pick(PickCommand),
     Propagate(PropagateCommand),
+    Undo(UndoCommand),
 }
 
 impl Command {
@@ -27,10 +32,15 @@ impl Command {
         } = self;
         match subcommand {
             Add(command) => map_err!(command.run().await, AddCommandRunFailed),
+            Adopt(command) => map_err!(command.run().await, AdoptCommandRunFailed),
+            Backups(command) => map_err!(command.run().await, BackupsCommandRunFailed),
             Create(command) => map_err!(command.run().await, CreateCommandRunFailed),
+            Drift(command) => map_err!(command.run().await, DriftCommandRunFailed),
             Init(command) => map_err!(command.run().await, InitCommandRunFailed),
             Merge(command) => map_err!(command.run().await, MergeCommandRunFailed),
+            Pick(command) => map_err!(command.run().await, PickCommandRunFailed),
             Propagate(command) => map_err!(command.run().await, PropagateCommandRunFailed),
+            Undo(command) => map_err!(command.run().await, UndoCommandRunFailed),
         }
     }
 }
@@ -39,23 +49,43 @@ impl Command {
 pub enum CommandRunError {
     #[error("failed to run add command")]
     AddCommandRunFailed { source: AddCommandRunError },
+    #[error("failed to run adopt command")]
+    AdoptCommandRunFailed { source: AdoptCommandRunError },
+    #[error("failed to run backups command")]
+    BackupsCommandRunFailed { source: BackupsCommandRunError },
     #[error("failed to run create command")]
     CreateCommandRunFailed { source: CreateCommandRunError },
+    #[error("failed to run drift command")]
+    DriftCommandRunFailed { source: DriftCommandRunError },
     #[error("failed to run init command")]
     InitCommandRunFailed { source: InitCommandRunError },
     #[error("failed to run merge command")]
     MergeCommandRunFailed { source: MergeCommandRunError },
+    #[error("failed to run pick command")]
+    PickCommandRunFailed { source: PickCommandRunError },
     #[error("failed to run propagate command")]
     PropagateCommandRunFailed { source: PropagateCommandRunError },
+    #[error("failed to run undo command")]
+    UndoCommandRunFailed { source: UndoCommandRunError },
 }
 
 mod add_command;
 pub use add_command::*;
+mod adopt_command;
+pub use adopt_command::*;
+mod backups_command;
+pub use backups_command::*;
 mod create_command;
 pub use create_command::*;
+mod drift_command;
+pub use drift_command::*;
 mod init_command;
 pub use init_command::*;
 mod merge_command;
 pub use merge_command::*;
+mod pick_command;
+pub use pick_command::*;
 mod propagate_command;
 pub use propagate_command::*;
+mod undo_command;
+pub use undo_command::*;
@@ -1,10 +1,9 @@
-use crate::{RepoName, UnwrapOrCurrentDirError, unwrap_or_current_dir};
+use crate::{TemplateConfig, TemplateConfigSaveError, TemplateRef, UnwrapOrCurrentDirError, unwrap_or_current_dir};
 use clap::{Parser, value_parser};
 use errgonomic::handle;
 use std::path::PathBuf;
 use std::process::ExitCode;
 use thiserror::Error;
-use url::Url;
 use xshell::{Shell, cmd};
 
 #[derive(Parser, Clone, Debug)]
@@ -13,9 +12,13 @@ pub struct AddCommand {
     #[arg(long, short, value_parser = value_parser!(PathBuf))]
     dir: Option<PathBuf>,
 
-    /// Template repo URL
-    #[arg(value_parser = value_parser!(Url))]
-    template: Url,
+    /// Subdirectory of the target repo to attach the template to (e.g. "crates/foo"); the template files are merged into this subdirectory instead of the repo root
+    #[arg(long)]
+    prefix: Option<String>,
+
+    /// Template repo URL, optionally followed by a branch and a subdirectory of the template repo (e.g. "https://github.com/example/templates#main:templates/rust-lib")
+    #[arg(value_parser = value_parser!(TemplateRef))]
+    template: TemplateRef,
 }
 
 impl AddCommand {
@@ -24,15 +27,21 @@ impl AddCommand {
         let Self {
             template,
             dir,
+            prefix,
         } = self;
 
         let dir = handle!(unwrap_or_current_dir(dir), UnwrapOrCurrentDirFailed);
         let sh = handle!(Shell::new(), ShellNewFailed);
         let sh = sh.with_current_dir(dir);
 
-        let remote_template_name_suffix = template.repo_name();
+        let TemplateRef {
+            url,
+            branch,
+            path,
+        } = &template;
+        let remote_template_name_suffix = template.name();
         let remote_template_name = format!("repoconf-{remote_template_name_suffix}");
-        let remote_template_url = template.as_str();
+        let remote_template_url = url.as_str();
 
         handle!(
             cmd!(sh, "git remote add {remote_template_name} {remote_template_url}").run_echo(),
@@ -40,6 +49,13 @@ impl AddCommand {
             remote_template_name,
             remote_template_url: remote_template_url
         );
+        let template_config = TemplateConfig {
+            prefix,
+            branch: branch.clone(),
+            path: path.clone(),
+            ..TemplateConfig::default()
+        };
+        handle!(template_config.save(&sh, &remote_template_name), TemplateConfigSaveFailed, remote_template_name);
         handle!(cmd!(sh, "git remote update {remote_template_name}").run_echo(), GitRemoteUpdateFailed, remote_template_name);
 
         Ok(ExitCode::SUCCESS)
@@ -54,6 +70,8 @@ pub enum AddCommandRunError {
     ShellNewFailed { source: xshell::Error },
     #[error("failed to add git remote '{remote_template_name}' with url '{remote_template_url}'")]
     GitRemoteAddFailed { source: xshell::Error, remote_template_name: String, remote_template_url: String },
+    #[error("failed to save the config of template '{remote_template_name}'")]
+    TemplateConfigSaveFailed { source: TemplateConfigSaveError, remote_template_name: String },
     #[error("failed to update git remote '{remote_template_name}'")]
     GitRemoteUpdateFailed { source: xshell::Error, remote_template_name: String },
 }
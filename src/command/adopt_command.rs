@@ -0,0 +1,239 @@
+use crate::{BranchNameStrategy, BranchNameStrategyToBranchNameError, GitRefsError, GitRemoteExistsError, HookOptions, HookPhase, HookRunnerRunError, HookRunnerWithTemplateError, IsCleanRepo, IsCleanRepoError, MergeCommand, MergeCommandRecordMergeResultError, MergeCommandRecordMergeStateError, MergeCommandResolveMergedRefError, Provisioner, ProvisionerLoadError, ProvisionerProvisionError, TemplateConfig, TemplateConfigSaveError, TemplateRef, UnixTimestampError, UnwrapOrCurrentDirError, git_refs, git_remote_exists, unix_timestamp, unwrap_or_current_dir};
+use clap::{ArgAction, Parser, value_parser};
+use errgonomic::{handle, handle_bool};
+use std::path::PathBuf;
+use std::process::ExitCode;
+use thiserror::Error;
+use xshell::{Shell, cmd};
+
+#[derive(Parser, Clone, Debug)]
+pub struct AdoptCommand {
+    /// Child repository directory (defaults to current directory)
+    #[arg(long, short, value_parser = value_parser!(PathBuf))]
+    pub dir: Option<PathBuf>,
+
+    /// Globs of the conflicting paths that should take the template version (all other conflicting paths keep the local version)
+    ///
+    /// Pass the flag once per glob: `--theirs '*.md' --theirs 'docs/**'`
+    #[arg(long, action = ArgAction::Append)]
+    pub theirs: Vec<String>,
+
+    /// Name of the remote branch to merge from
+    ///
+    /// If you pass "-", the command will determine the branch automatically: use "main" if exists, use "master" if exists.
+    #[arg(long = "remote-branch", short = 'r', default_value = "-")]
+    pub remote_branch_strategy: BranchNameStrategy,
+
+    /// Template repo URL, optionally followed by a branch and a subdirectory of the template repo (e.g. "https://github.com/example/templates#main:templates/rust-lib")
+    #[arg(value_parser = value_parser!(TemplateRef))]
+    pub template: TemplateRef,
+
+    #[command(flatten)]
+    pub hook_options: HookOptions,
+}
+
+impl AdoptCommand {
+    /// Adds the template remote to an existing repository and commits a baseline merge of the template history
+    ///
+    /// The histories are unrelated, so the merge usually conflicts on every shared file. The conflicts are resolved to the local version, except the paths that match the `--theirs` globs. Subsequent merges only bring the template changes made after the baseline.
+    pub async fn run(self) -> Result<ExitCode, AdoptCommandRunError> {
+        use AdoptCommandRunError::*;
+        let Self {
+            dir,
+            theirs,
+            remote_branch_strategy,
+            template,
+            hook_options,
+        } = self;
+
+        let dir = handle!(unwrap_or_current_dir(dir), UnwrapOrCurrentDirFailed);
+        let sh_dir = handle!(Shell::new(), ShellNewFailed).with_current_dir(&dir);
+
+        let is_clean = handle!(sh_dir.is_clean_repo(), IsCleanRepoFailed);
+        handle_bool!(!is_clean, RepositoryNotClean, dir);
+
+        let local_branch_name = handle!(cmd!(sh_dir, "git symbolic-ref --short HEAD").read(), GitCurrentBranchReadFailed);
+
+        let remote_template_name = format!("repoconf-{name}", name = template.name());
+        let TemplateRef {
+            url,
+            branch,
+            path,
+        } = template;
+        let remote_template_url = url.as_str();
+        let remote_exists = handle!(git_remote_exists(&sh_dir, &remote_template_name, remote_template_url), GitRemoteExistsFailed, remote_template_url: remote_template_url);
+        if !remote_exists {
+            handle!(
+                cmd!(sh_dir, "git remote add {remote_template_name} {remote_template_url}").run_echo(),
+                GitRemoteAddFailed,
+                remote_template_name,
+                remote_template_url: remote_template_url
+            );
+        }
+        let template_config = TemplateConfig {
+            branch: branch.clone(),
+            path: path.clone(),
+            ..TemplateConfig::default()
+        };
+        handle!(template_config.save(&sh_dir, &remote_template_name), TemplateConfigSaveFailed, remote_template_name);
+        handle!(cmd!(sh_dir, "git remote update {remote_template_name}").run_echo(), GitRemoteUpdateFailed, remote_template_name);
+
+        let remote_branch_name = match branch {
+            Some(branch) => branch,
+            None => {
+                let refs = handle!(git_refs(&sh_dir), GitRefsFailed);
+                let remote_prefix = format!("refs/remotes/{remote_template_name}");
+                handle!(remote_branch_strategy.to_branch_name(&remote_prefix, &refs), RemoteBranchNameResolveFailed, prefix: remote_prefix, strategy: remote_branch_strategy)
+            }
+        };
+        let merged_ref = handle!(MergeCommand::resolve_merged_ref(&sh_dir, &remote_template_name, &remote_branch_name, path.as_deref()), ResolveMergedRefFailed, remote_template_name);
+
+        let backup_timestamp = handle!(unix_timestamp(), UnixTimestampFailed);
+        handle!(MergeCommand::record_merge_state(&sh_dir, &local_branch_name, backup_timestamp), RecordMergeStateFailed);
+
+        // The merge is expected to conflict, so its exit status is checked via the merge state below
+        handle!(
+            cmd!(sh_dir, "git merge --allow-unrelated-histories --no-commit --no-ff {merged_ref}")
+                .ignore_status()
+                .run_echo(),
+            GitMergeFailed,
+            merged_ref
+        );
+        let merge_head_path = handle!(cmd!(sh_dir, "git rev-parse --path-format=absolute --git-path MERGE_HEAD").read(), GitMergeHeadPathFailed);
+        handle_bool!(!sh_dir.path_exists(&merge_head_path), MergeNotStarted, merged_ref);
+
+        let unmerged_paths = handle!(cmd!(sh_dir, "git diff --name-only --diff-filter=U").read(), GitUnmergedPathsReadFailed);
+        let theirs_matches = if theirs.is_empty() || unmerged_paths.is_empty() {
+            String::new()
+        } else {
+            let pathspecs = theirs
+                .iter()
+                .map(|glob| format!(":(top,glob){glob}"))
+                .collect::<Vec<_>>();
+            handle!(cmd!(sh_dir, "git diff --name-only --diff-filter=U -- {pathspecs...}").read(), GitUnmergedPathsReadFailed)
+        };
+        let (theirs_paths, ours_paths): (Vec<&str>, Vec<&str>) = unmerged_paths.lines().partition(|unmerged_path| {
+            theirs_matches
+                .lines()
+                .any(|theirs_match| theirs_match == *unmerged_path)
+        });
+
+        handle!(Self::resolve_conflicts(&sh_dir, &ours_paths, "HEAD"), ResolveConflictsFailed, side: "ours");
+        handle!(Self::resolve_conflicts(&sh_dir, &theirs_paths, "MERGE_HEAD"), ResolveConflictsFailed, side: "theirs");
+        ours_paths
+            .iter()
+            .for_each(|path| eprintln!("[OURS] {path}"));
+        theirs_paths
+            .iter()
+            .for_each(|path| eprintln!("[THEIRS] {path}"));
+
+        let hook_runner = handle!(
+            hook_options
+                .hook_runner()
+                .with_template(&sh_dir, &remote_template_name),
+            HookRunnerWithTemplateFailed,
+            remote_template_name
+        )
+        .with_env("REPOCONF_COMMAND", "adopt")
+        .with_env("REPOCONF_NO_PUSH", "0")
+        .with_env("REPOCONF_LOCAL_BRANCH", &local_branch_name)
+        .with_env("REPOCONF_REMOTE_BRANCH", &remote_branch_name)
+        .with_env("REPOCONF_MERGED_RANGE", format!("HEAD..{merged_ref}"));
+        let provisioner = handle!(Provisioner::load(&sh_dir), ProvisionerLoadFailed);
+        handle!(provisioner.provision(&sh_dir), ProvisionFailed);
+        handle!(hook_runner.run(&sh_dir, HookPhase::PreMergeCommit), RunPreMergeCommitHooksFailed);
+        let message = format!("Adopt template {remote_template_name}");
+        handle!(cmd!(sh_dir, "git commit -m {message}").run_echo(), GitCommitFailed);
+        // The baseline merge isn't pushed, so it can be undone with `repoconf undo` like a merge with `--no-push`
+        handle!(MergeCommand::record_merge_result(&sh_dir, &sh_dir), RecordMergeResultFailed);
+
+        Ok(ExitCode::SUCCESS)
+    }
+
+    /// Resolves the conflicting `paths` to their version in `source_ref` (the paths that don't exist in `source_ref` are removed)
+    ///
+    /// PRUNING: Discards the other side of the conflicts in `paths`, because the user has chosen the side to keep.
+    fn resolve_conflicts(sh_dir: &Shell, paths: &[&str], source_ref: &str) -> Result<(), AdoptCommandResolveConflictsError> {
+        use AdoptCommandResolveConflictsError::*;
+        if paths.is_empty() {
+            return Ok(());
+        }
+        let existing_paths = handle!(cmd!(sh_dir, "git --literal-pathspecs ls-tree -r --name-only {source_ref} -- {paths...}").read(), GitLsTreeFailed, source_ref);
+        let (restored_paths, removed_paths): (Vec<&str>, Vec<&str>) = paths.iter().copied().partition(|path| {
+            existing_paths
+                .lines()
+                .any(|existing_path| existing_path == *path)
+        });
+        if !restored_paths.is_empty() {
+            handle!(cmd!(sh_dir, "git --literal-pathspecs checkout {source_ref} -- {restored_paths...}").run_echo(), GitCheckoutFailed, source_ref);
+        }
+        if !removed_paths.is_empty() {
+            handle!(cmd!(sh_dir, "git --literal-pathspecs rm -q -f -- {removed_paths...}").run_echo(), GitRmFailed);
+        }
+        Ok(())
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum AdoptCommandRunError {
+    #[error("failed to resolve the target directory")]
+    UnwrapOrCurrentDirFailed { source: UnwrapOrCurrentDirError },
+    #[error("failed to create a shell instance")]
+    ShellNewFailed { source: xshell::Error },
+    #[error("failed to check repository status")]
+    IsCleanRepoFailed { source: IsCleanRepoError },
+    #[error("repository '{dir}' has uncommitted changes")]
+    RepositoryNotClean { dir: PathBuf },
+    #[error("failed to read the current branch (adopting a template requires a checked out branch)")]
+    GitCurrentBranchReadFailed { source: xshell::Error },
+    #[error("failed to check whether template remote '{remote_template_url}' exists")]
+    GitRemoteExistsFailed { source: GitRemoteExistsError, remote_template_url: String },
+    #[error("failed to add git remote '{remote_template_name}' with url '{remote_template_url}'")]
+    GitRemoteAddFailed { source: xshell::Error, remote_template_name: String, remote_template_url: String },
+    #[error("failed to save the config of template '{remote_template_name}'")]
+    TemplateConfigSaveFailed { source: TemplateConfigSaveError, remote_template_name: String },
+    #[error("failed to update git remote '{remote_template_name}'")]
+    GitRemoteUpdateFailed { source: xshell::Error, remote_template_name: String },
+    #[error("failed to read git refs")]
+    GitRefsFailed { source: GitRefsError },
+    #[error("failed to resolve remote branch name for prefix '{prefix}'")]
+    RemoteBranchNameResolveFailed { source: BranchNameStrategyToBranchNameError, prefix: String, strategy: BranchNameStrategy },
+    #[error("failed to resolve the ref to merge from template remote '{remote_template_name}'")]
+    ResolveMergedRefFailed { source: MergeCommandResolveMergedRefError, remote_template_name: String },
+    #[error("failed to compute the backup timestamp")]
+    UnixTimestampFailed { source: UnixTimestampError },
+    #[error("failed to record the merge state")]
+    RecordMergeStateFailed { source: MergeCommandRecordMergeStateError },
+    #[error("failed to merge '{merged_ref}'")]
+    GitMergeFailed { source: xshell::Error, merged_ref: String },
+    #[error("failed to resolve the merge state path")]
+    GitMergeHeadPathFailed { source: xshell::Error },
+    #[error("failed to start the merge of '{merged_ref}'")]
+    MergeNotStarted { merged_ref: String },
+    #[error("failed to read the unmerged paths")]
+    GitUnmergedPathsReadFailed { source: xshell::Error },
+    #[error("failed to resolve the conflicts to '{side}'")]
+    ResolveConflictsFailed { source: AdoptCommandResolveConflictsError, side: String },
+    #[error("failed to prepare the hook environment of template remote '{remote_template_name}'")]
+    HookRunnerWithTemplateFailed { source: HookRunnerWithTemplateError, remote_template_name: String },
+    #[error("failed to load the provisioner config")]
+    ProvisionerLoadFailed { source: ProvisionerLoadError },
+    #[error("failed to provision the toolchain before committing the baseline merge")]
+    ProvisionFailed { source: ProvisionerProvisionError },
+    #[error("failed to run the pre-merge-commit hooks")]
+    RunPreMergeCommitHooksFailed { source: HookRunnerRunError },
+    #[error("failed to commit the baseline merge")]
+    GitCommitFailed { source: xshell::Error },
+    #[error("failed to record the result of the baseline merge")]
+    RecordMergeResultFailed { source: MergeCommandRecordMergeResultError },
+}
+
+#[derive(Error, Debug)]
+pub enum AdoptCommandResolveConflictsError {
+    #[error("failed to list the conflicting paths that exist in '{source_ref}'")]
+    GitLsTreeFailed { source: xshell::Error, source_ref: String },
+    #[error("failed to check out the conflicting paths from '{source_ref}'")]
+    GitCheckoutFailed { source: xshell::Error, source_ref: String },
+    #[error("failed to remove the conflicting paths")]
+    GitRmFailed { source: xshell::Error },
+}
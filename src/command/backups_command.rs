@@ -0,0 +1,43 @@
+use BackupsSubcommand::*;
+use clap::Parser;
+use errgonomic::map_err;
+use std::process::ExitCode;
+use thiserror::Error;
+
+#[derive(Parser, Clone, Debug)]
+pub struct BackupsCommand {
+    #[command(subcommand)]
+    subcommand: BackupsSubcommand,
+}
+
+#[derive(clap::Subcommand, Clone, Debug)]
+pub enum BackupsSubcommand {
+    List(BackupsListCommand),
+    Prune(BackupsPruneCommand),
+}
+
+impl BackupsCommand {
+    pub async fn run(self) -> Result<ExitCode, BackupsCommandRunError> {
+        use BackupsCommandRunError::*;
+        let Self {
+            subcommand,
+        } = self;
+        match subcommand {
+            List(command) => map_err!(command.run().await, BackupsListCommandRunFailed),
+            Prune(command) => map_err!(command.run().await, BackupsPruneCommandRunFailed),
+        }
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum BackupsCommandRunError {
+    #[error("failed to run backups list command")]
+    BackupsListCommandRunFailed { source: BackupsListCommandRunError },
+    #[error("failed to run backups prune command")]
+    BackupsPruneCommandRunFailed { source: BackupsPruneCommandRunError },
+}
+
+mod backups_list_command;
+pub use backups_list_command::*;
+mod backups_prune_command;
+pub use backups_prune_command::*;
@@ -0,0 +1,45 @@
+use crate::{REPOCONF_BACKUP_REF_PREFIX, UnwrapOrCurrentDirError, unwrap_or_current_dir};
+use clap::{Parser, value_parser};
+use errgonomic::handle;
+use std::path::PathBuf;
+use std::process::ExitCode;
+use thiserror::Error;
+use xshell::{Shell, cmd};
+
+#[derive(Parser, Clone, Debug)]
+pub struct BackupsListCommand {
+    /// Child repository directory (defaults to current directory)
+    #[arg(long, short, value_parser = value_parser!(PathBuf))]
+    pub dir: Option<PathBuf>,
+}
+
+impl BackupsListCommand {
+    pub async fn run(self) -> Result<ExitCode, BackupsListCommandRunError> {
+        use BackupsListCommandRunError::*;
+        let Self {
+            dir,
+        } = self;
+
+        let dir = handle!(unwrap_or_current_dir(dir), UnwrapOrCurrentDirFailed);
+        let sh_dir = handle!(Shell::new(), ShellNewFailed).with_current_dir(&dir);
+
+        let backup_ref_prefix = format!("{REPOCONF_BACKUP_REF_PREFIX}/");
+        // Backup refs are named after Unix timestamps, so sorting by ref name sorts them by time (newest first)
+        let backups = handle!(cmd!(sh_dir, "git for-each-ref --sort=-refname --format='%(refname) %(objectname:short) %(subject)' {backup_ref_prefix}").read(), GitForEachRefFailed, dir);
+        if !backups.is_empty() {
+            println!("{backups}");
+        }
+
+        Ok(ExitCode::SUCCESS)
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum BackupsListCommandRunError {
+    #[error("failed to resolve the target directory")]
+    UnwrapOrCurrentDirFailed { source: UnwrapOrCurrentDirError },
+    #[error("failed to create a shell instance")]
+    ShellNewFailed { source: xshell::Error },
+    #[error("failed to list backups in '{dir}'")]
+    GitForEachRefFailed { source: xshell::Error, dir: PathBuf },
+}
@@ -0,0 +1,54 @@
+use crate::{REPOCONF_BACKUP_REF_PREFIX, UnwrapOrCurrentDirError, unwrap_or_current_dir};
+use clap::{Parser, value_parser};
+use errgonomic::handle;
+use std::path::PathBuf;
+use std::process::ExitCode;
+use thiserror::Error;
+use xshell::{Shell, cmd};
+
+#[derive(Parser, Clone, Debug)]
+pub struct BackupsPruneCommand {
+    /// Child repository directory (defaults to current directory)
+    #[arg(long, short, value_parser = value_parser!(PathBuf))]
+    pub dir: Option<PathBuf>,
+
+    /// Number of the newest backups to keep
+    #[arg(long, short)]
+    pub keep: usize,
+}
+
+impl BackupsPruneCommand {
+    /// PRUNING: Deletes all backup refs except the newest `keep` ones, because the user has requested to prune them. The commits remain reachable via the reflog until it expires.
+    pub async fn run(self) -> Result<ExitCode, BackupsPruneCommandRunError> {
+        use BackupsPruneCommandRunError::*;
+        let Self {
+            dir,
+            keep,
+        } = self;
+
+        let dir = handle!(unwrap_or_current_dir(dir), UnwrapOrCurrentDirFailed);
+        let sh_dir = handle!(Shell::new(), ShellNewFailed).with_current_dir(&dir);
+
+        let backup_ref_prefix = format!("{REPOCONF_BACKUP_REF_PREFIX}/");
+        // Backup refs are named after Unix timestamps, so sorting by ref name sorts them by time (newest first)
+        let backup_refs = handle!(cmd!(sh_dir, "git for-each-ref --sort=-refname --format='%(refname)' {backup_ref_prefix}").read(), GitForEachRefFailed, dir);
+        backup_refs.lines().skip(keep).try_for_each(|backup_ref| {
+            handle!(cmd!(sh_dir, "git update-ref -d {backup_ref}").run_echo(), GitUpdateRefDeleteFailed, backup_ref);
+            Ok(())
+        })?;
+
+        Ok(ExitCode::SUCCESS)
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum BackupsPruneCommandRunError {
+    #[error("failed to resolve the target directory")]
+    UnwrapOrCurrentDirFailed { source: UnwrapOrCurrentDirError },
+    #[error("failed to create a shell instance")]
+    ShellNewFailed { source: xshell::Error },
+    #[error("failed to list backups in '{dir}'")]
+    GitForEachRefFailed { source: xshell::Error, dir: PathBuf },
+    #[error("failed to delete backup '{backup_ref}'")]
+    GitUpdateRefDeleteFailed { source: xshell::Error, backup_ref: String },
+}
@@ -1,42 +1,48 @@
-use crate::{InitCommand, InitCommandRunError, RepoName, Visibility};
-use clap::{Parser, value_parser};
-use errgonomic::{handle, handle_bool};
+use crate::{GitConfigGetAll, GitConfigGetAllError, HookOptions, InitCommand, InitCommandRunError, TaskError, TemplateRef, TemplateRefFromStrError, Visibility, task};
+use clap::{Parser, ValueEnum, value_parser};
+use demand::{DemandOption, Input, Select};
+use errgonomic::{handle, handle_bool, handle_opt};
+use itertools::Itertools;
 use std::io;
+use std::iter::once;
 use std::path::PathBuf;
 use std::process::{ExitCode, Output};
 use thiserror::Error;
-use url::Url;
 use xshell::{Shell, cmd};
 
 #[derive(Parser, Clone, Debug)]
 pub struct CreateCommand {
+    /// Prompt for the missing arguments (the templates are offered from the `repoconf.template` git config key), preview the plan and confirm it before creating anything
+    #[arg(long, short, conflicts_with = "non_interactive")]
+    interactive: bool,
+
     /// If the target repository exists, use it
     #[arg(long, short)]
     use_existing: bool,
 
-    /// Don't run the post-init script
+    /// Don't run the post-init hooks
     #[arg(long, short)]
     skip_post_init: bool,
 
-    /// Additional post-init script to run after the template's post-init script
+    /// Additional post-init hook to run after the template's post-init hooks
     #[arg(long, value_parser = value_parser!(PathBuf))]
     post_init: Option<PathBuf>,
 
     /// Repo visibility
-    #[arg(value_enum, long, short)]
-    visibility: Visibility,
+    #[arg(value_enum, long, short, required_unless_present = "interactive")]
+    visibility: Option<Visibility>,
 
-    /// Template repo URL
-    #[arg(value_parser = value_parser!(Url))]
-    template_url: Url,
+    /// Template repo URL, optionally followed by a branch and a subdirectory of the template repo (e.g. "https://github.com/example/templates#main:templates/rust-lib")
+    #[arg(value_parser = value_parser!(TemplateRef), required_unless_present = "interactive")]
+    template_url: Option<TemplateRef>,
 
     /// Owner of the new repository
-    #[arg()]
-    repo_owner: String,
+    #[arg(required_unless_present = "interactive")]
+    repo_owner: Option<String>,
 
     /// Name of the new repository
-    #[arg()]
-    repo_name: String,
+    #[arg(required_unless_present = "interactive")]
+    repo_name: Option<String>,
 
     /// Name of the origin remote
     #[arg(long, short, default_value = "origin")]
@@ -47,14 +53,39 @@ pub struct CreateCommand {
     branch_name: String,
 
     /// Directory to clone the new repository to
-    #[arg(value_parser = value_parser!(PathBuf))]
-    dir: PathBuf,
+    #[arg(value_parser = value_parser!(PathBuf), required_unless_present = "interactive")]
+    dir: Option<PathBuf>,
+
+    /// Value of a template variable (e.g. "license=MIT"); the `name` and `owner` variables default to the repo name and the repo owner
+    #[arg(long = "var", value_name = "NAME=VALUE")]
+    vars: Vec<String>,
+
+    #[command(flatten)]
+    hook_options: HookOptions,
 }
 
 impl CreateCommand {
+    /// Git config key with the template URLs offered by `--interactive` (e.g. `git config --global --add repoconf.template https://github.com/example/repoconf-rust-lib`)
+    pub const TEMPLATES_KEY: &'static str = "repoconf.template";
+
     pub async fn run(self) -> Result<ExitCode, CreateCommandRunError> {
         use CreateCommandRunError::*;
+        let sh_cwd = handle!(Shell::new(), ShellNewFailed);
+
+        let command = if self.interactive {
+            match handle!(self.prompt(&sh_cwd), PromptFailed) {
+                Some(command) => command,
+                None => {
+                    eprintln!("[SKIP] repository creation has been cancelled");
+                    return Ok(ExitCode::FAILURE);
+                }
+            }
+        } else {
+            self
+        };
+
         let Self {
+            interactive: _,
             use_existing,
             visibility,
             template_url,
@@ -65,41 +96,26 @@ impl CreateCommand {
             skip_post_init,
             post_init,
             dir,
-        } = self;
+            vars,
+            hook_options,
+        } = command;
 
-        let sh_cwd = handle!(Shell::new(), ShellNewFailed);
+        // clap requires these arguments unless `--interactive` is passed, and `prompt` fills them in otherwise
+        let visibility = handle_opt!(visibility, ArgumentMissing, name: "visibility");
+        let template_url = handle_opt!(template_url, ArgumentMissing, name: "template_url");
+        let repo_owner = handle_opt!(repo_owner, ArgumentMissing, name: "repo_owner");
+        let repo_name = handle_opt!(repo_name, ArgumentMissing, name: "repo_name");
+        let dir = handle_opt!(dir, ArgumentMissing, name: "dir");
 
         let repo_name_full = format!("{repo_owner}/{repo_name}");
-        let template_name = template_url.repo_name().to_string();
+        let template_name = template_url.name().to_string();
         let visibility_arg = visibility.as_arg();
 
-        let repo_view_cmd = cmd!(&sh_cwd, "gh repo view --json name {repo_name_full}");
-        eprintln!("$ {repo_view_cmd}");
-        let mut repo_view_command = repo_view_cmd.to_command();
-        let repo_view_output = handle!(repo_view_command.output(), RepoViewOutputFailed, repo_name_full);
-        match repo_view_output.status.code() {
-            Some(0) => {
-                handle_bool!(!use_existing, RepositoryAlreadyExists, repo_name_full);
-            }
-            Some(1) => {
-                let repository_not_found = repo_view_output
-                    .stderr
-                    .starts_with("GraphQL: Could not resolve to a Repository".as_bytes());
-                if repository_not_found {
-                    handle!(cmd!(sh_cwd, "gh repo create {visibility_arg} {repo_name_full}").run_echo(), RepoCreateFailed, repo_name_full, visibility_arg);
-                } else {
-                    return Err(RepoViewUnexpectedOutput {
-                        repo_name_full,
-                        output: repo_view_output,
-                    });
-                }
-            }
-            _ => {
-                return Err(RepoViewUnexpectedOutput {
-                    repo_name_full,
-                    output: repo_view_output,
-                });
-            }
+        let repo_exists = handle!(Self::repo_exists(&sh_cwd, &repo_name_full), RepoExistsFailed, repo_name_full);
+        if repo_exists {
+            handle_bool!(!use_existing, RepositoryAlreadyExists, repo_name_full);
+        } else {
+            handle!(cmd!(sh_cwd, "gh repo create {visibility_arg} {repo_name_full}").run_echo(), RepoCreateFailed, repo_name_full, visibility_arg);
         }
 
         let dir_exists = handle!(dir.try_exists(), DirExistsCheckFailed, dir);
@@ -111,42 +127,170 @@ impl CreateCommand {
             handle!(cmd!(sh_cwd, "gh repo clone {repo_name_full} {dir} -- --origin {remote_name}").run_echo(), RepoCloneFailed, repo_name_full, dir, remote_name);
         }
 
-        let sh_dir = {
-            let mut sh_dir = sh_cwd.with_current_dir(&dir);
-            sh_dir.set_var("REPOCONF_VISIBILITY", visibility.to_string());
-            sh_dir.set_var("REPOCONF_TEMPLATE", template_url.to_string());
-            sh_dir.set_var("REPOCONF_REPO_OWNER", &repo_owner);
-            sh_dir.set_var("REPOCONF_REPO_NAME", &repo_name);
-            sh_dir
-        };
+        let sh_dir = sh_cwd.with_current_dir(&dir);
 
         handle!(cmd!(sh_dir, "gh repo set-default {repo_name_full}").run_echo(), RepoSetDefaultFailed, repo_name_full);
 
+        // InitCommand runs the hooks in its own shell, so the variables are passed explicitly
+        // The explicit `--var` flags come last, so they take precedence over the repo name and owner
+        let vars = [format!("name={repo_name}"), format!("owner={repo_owner}")]
+            .into_iter()
+            .chain(vars)
+            .collect();
+        let hook_env = vec![
+            ("REPOCONF_COMMAND".to_string(), "create".to_string()),
+            ("REPOCONF_VISIBILITY".to_string(), visibility.to_string()),
+            ("REPOCONF_REPO_OWNER".to_string(), repo_owner),
+            ("REPOCONF_REPO_NAME".to_string(), repo_name),
+        ];
         let init_cmd = InitCommand {
             remote_name,
             branch_name,
             skip_post_init,
             post_init,
+            prefix: None,
+            vars,
             template_name,
             template_url,
             dir,
+            hook_env,
+            hook_options,
         };
         handle!(init_cmd.run().await, InitCommandRunFailed, repo_name_full);
 
         Ok(ExitCode::SUCCESS)
     }
+
+    /// Returns true if the repository exists on the forge
+    fn repo_exists(sh: &Shell, repo_name_full: &str) -> Result<bool, CreateCommandRepoExistsError> {
+        use CreateCommandRepoExistsError::*;
+        let repo_view_cmd = cmd!(sh, "gh repo view --json name {repo_name_full}");
+        eprintln!("$ {repo_view_cmd}");
+        let mut repo_view_command = repo_view_cmd.to_command();
+        let output = handle!(repo_view_command.output(), RepoViewOutputFailed);
+        let repository_not_found = output.status.code() == Some(1)
+            && output
+                .stderr
+                .starts_with("GraphQL: Could not resolve to a Repository".as_bytes());
+        handle_bool!(!output.status.success() && !repository_not_found, RepoViewUnexpectedOutput, output);
+        Ok(output.status.success())
+    }
+
+    /// Prompts for the arguments that haven't been passed, previews the plan and asks for a confirmation
+    ///
+    /// Returns `None` if the plan has been declined.
+    fn prompt(self, sh: &Shell) -> Result<Option<Self>, CreateCommandPromptError> {
+        use CreateCommandPromptError::*;
+        let template_url = match self.template_url {
+            Some(template_url) => template_url,
+            None => {
+                let templates = handle!(sh.git_config_get_all(Self::TEMPLATES_KEY), GitConfigGetAllFailed);
+                let template_url = if templates.is_empty() {
+                    let title = format!("Template URL (add the frequently used templates with `git config --global --add {} <url>`)", Self::TEMPLATES_KEY);
+                    handle!(Input::new(&title).run(), InputRunFailed, field: "template_url")
+                } else {
+                    let select = Select::new("Template")
+                        .filterable(true)
+                        .options(templates.into_iter().map(DemandOption::new).collect());
+                    handle!(select.run(), SelectRunFailed, field: "template_url")
+                };
+                handle!(template_url.parse::<TemplateRef>(), TemplateRefParseFailed)
+            }
+        };
+        let repo_owner = match self.repo_owner {
+            Some(repo_owner) => repo_owner,
+            None => {
+                let user = handle!(cmd!(sh, "gh api user --jq .login").read(), GhApiUserFailed);
+                let orgs = handle!(cmd!(sh, "gh api user/orgs --jq .[].login").read(), GhApiUserOrgsFailed);
+                let owners = once(user)
+                    .chain(orgs.lines().map(ToOwned::to_owned))
+                    .collect_vec();
+                let select = Select::new("Owner").options(owners.into_iter().map(DemandOption::new).collect());
+                handle!(select.run(), SelectRunFailed, field: "repo_owner")
+            }
+        };
+        let visibility = match self.visibility {
+            Some(visibility) => visibility,
+            None => {
+                let select = Select::new("Visibility").options(
+                    Visibility::value_variants()
+                        .iter()
+                        .copied()
+                        .map(DemandOption::new)
+                        .collect(),
+                );
+                handle!(select.run(), SelectRunFailed, field: "visibility")
+            }
+        };
+        let repo_name = match self.repo_name {
+            Some(repo_name) => repo_name,
+            None => loop {
+                let repo_name = handle!(Input::new("Repository name").run(), InputRunFailed, field: "repo_name");
+                if !Self::is_valid_repo_name(&repo_name) {
+                    eprintln!("[WARN] '{repo_name}' is not a valid repository name (use letters, digits, '-', '_' and '.')");
+                    continue;
+                }
+                let repo_name_full = format!("{repo_owner}/{repo_name}");
+                let repo_exists = handle!(Self::repo_exists(sh, &repo_name_full), RepoExistsFailed, repo_name_full);
+                if repo_exists && !self.use_existing {
+                    eprintln!("[WARN] repository '{repo_name_full}' already exists (pass --use-existing to initialize it)");
+                    continue;
+                }
+                break repo_name;
+            },
+        };
+        let dir = match self.dir {
+            Some(dir) => dir,
+            None => {
+                let dir = handle!(Input::new("Directory").placeholder(&repo_name).run(), InputRunFailed, field: "dir");
+                PathBuf::from(if dir.is_empty() { repo_name.as_str() } else { dir.as_str() })
+            }
+        };
+
+        let repo_name_full = format!("{repo_owner}/{repo_name}");
+        let action = if self.use_existing { "create (or use the existing)" } else { "create" };
+        eprintln!("[PLAN] {action} {visibility} repository '{repo_name_full}'");
+        eprintln!("[PLAN] clone it into '{}' with remote '{}'", dir.display(), self.remote_name);
+        eprintln!("[PLAN] initialize branch '{}' from template '{template_url}'", self.branch_name);
+        let confirmed = handle!(task(format!("Create repository '{repo_name_full}'?")), TaskFailed);
+        if !confirmed {
+            return Ok(None);
+        }
+
+        Ok(Some(Self {
+            interactive: false,
+            visibility: Some(visibility),
+            template_url: Some(template_url),
+            repo_owner: Some(repo_owner),
+            repo_name: Some(repo_name),
+            dir: Some(dir),
+            ..self
+        }))
+    }
+
+    /// Returns true if `repo_name` is accepted by the forge as a repository name
+    fn is_valid_repo_name(repo_name: &str) -> bool {
+        !repo_name.is_empty()
+            && repo_name != "."
+            && repo_name != ".."
+            && repo_name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+    }
 }
 
 #[derive(Error, Debug)]
 pub enum CreateCommandRunError {
     #[error("failed to create a shell instance")]
     ShellNewFailed { source: xshell::Error },
-    #[error("failed to view repository '{repo_name_full}'")]
-    RepoViewOutputFailed { source: io::Error, repo_name_full: String },
+    #[error("failed to prompt for the arguments")]
+    PromptFailed { source: CreateCommandPromptError },
+    #[error("missing argument '{name}'")]
+    ArgumentMissing { name: String },
+    #[error("failed to check whether repository '{repo_name_full}' exists")]
+    RepoExistsFailed { source: CreateCommandRepoExistsError, repo_name_full: String },
     #[error("repository '{repo_name_full}' already exists")]
     RepositoryAlreadyExists { repo_name_full: String },
-    #[error("unexpected output while viewing repository '{repo_name_full}'")]
-    RepoViewUnexpectedOutput { output: Output, repo_name_full: String },
     #[error("failed to create repository '{repo_name_full}' with visibility '{visibility_arg}'")]
     RepoCreateFailed { source: xshell::Error, repo_name_full: String, visibility_arg: String },
     #[error("failed to check whether directory '{dir}' exists")]
@@ -162,3 +306,31 @@ pub enum CreateCommandRunError {
     #[error("failed to initialize repository '{repo_name_full}'")]
     InitCommandRunFailed { source: InitCommandRunError, repo_name_full: String },
 }
+
+#[derive(Error, Debug)]
+pub enum CreateCommandRepoExistsError {
+    #[error("failed to view repository")]
+    RepoViewOutputFailed { source: io::Error },
+    #[error("unexpected output while viewing repository")]
+    RepoViewUnexpectedOutput { output: Output },
+}
+
+#[derive(Error, Debug)]
+pub enum CreateCommandPromptError {
+    #[error("failed to read the configured templates")]
+    GitConfigGetAllFailed { source: GitConfigGetAllError },
+    #[error("failed to prompt for '{field}'")]
+    InputRunFailed { source: io::Error, field: String },
+    #[error("failed to prompt for '{field}'")]
+    SelectRunFailed { source: io::Error, field: String },
+    #[error("failed to parse the template URL")]
+    TemplateRefParseFailed { source: TemplateRefFromStrError },
+    #[error("failed to read the current user")]
+    GhApiUserFailed { source: xshell::Error },
+    #[error("failed to read the organizations of the current user")]
+    GhApiUserOrgsFailed { source: xshell::Error },
+    #[error("failed to check whether repository '{repo_name_full}' exists")]
+    RepoExistsFailed { source: CreateCommandRepoExistsError, repo_name_full: String },
+    #[error("failed to confirm the plan")]
+    TaskFailed { source: TaskError },
+}
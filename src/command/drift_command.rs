@@ -0,0 +1,225 @@
+use crate::{BranchNameStrategy, BranchNameStrategyToBranchNameError, GitRefsError, GitRemoteNames, GitRemoteNamesError, MergeCommand, MergeCommandSplitCommitError, MergeCommandSyncedCommitError, SyncMode, TemplateConfig, TemplateConfigLoadError, UnwrapOrCurrentDirError, git_refs, unwrap_or_current_dir};
+use clap::{Parser, value_parser};
+use errgonomic::{ErrVec, handle, handle_iter};
+use itertools::Itertools;
+use std::path::PathBuf;
+use std::process::ExitCode;
+use thiserror::Error;
+use xshell::{Shell, cmd};
+
+#[derive(Parser, Clone, Debug)]
+pub struct DriftCommand {
+    /// Child repository directory (defaults to current directory)
+    #[arg(long, short, value_parser = value_parser!(PathBuf))]
+    pub dir: Option<PathBuf>,
+
+    /// Name of the remote branch to compare with
+    ///
+    /// If you pass "-", the command will determine the branch automatically: use "main" if exists, use "master" if exists.
+    ///
+    /// Note that this is applied to all remotes
+    #[arg(long = "remote-branch", short = 'r', default_value = "-")]
+    pub remote_branch_strategy: BranchNameStrategy,
+}
+
+impl DriftCommand {
+    /// Lists the files that differ from the last merged template revision
+    ///
+    /// The files that have a `merge=ours` attribute are reported as overridden, the files excluded by the include/exclude config are reported as filtered, the other files are reported as drift. For the templates with the `files` sync mode, only the managed files are compared with the last synced revision. Exits with a failure code if any file has drifted.
+    pub async fn run(self) -> Result<ExitCode, DriftCommandRunError> {
+        use DriftCommandRunError::*;
+        let Self {
+            dir,
+            remote_branch_strategy,
+        } = self;
+
+        let dir = handle!(unwrap_or_current_dir(dir), UnwrapOrCurrentDirFailed);
+        let sh_dir = handle!(Shell::new(), ShellNewFailed).with_current_dir(&dir);
+
+        let remotes = handle!(sh_dir.git_remote_names(), GitRemoteNamesFailed)
+            .filter(|name| name.starts_with("repoconf"))
+            .collect_vec();
+        let refs = handle!(git_refs(&sh_dir), GitRefsFailed);
+
+        let drift_counts = handle_iter!(
+            remotes
+                .iter()
+                .map(|remote| Self::report_remote(&sh_dir, &remote_branch_strategy, &refs, remote)),
+            ReportRemotesFailed
+        );
+
+        if drift_counts.iter().any(|drift_count| *drift_count != 0) {
+            Ok(ExitCode::FAILURE)
+        } else {
+            Ok(ExitCode::SUCCESS)
+        }
+    }
+
+    /// Prints the files that differ from the last merged (or synced) revision of `remote` and returns the number of drifted files
+    ///
+    /// Doesn't write any refs: the history of the template subdirectory is split on the fly.
+    fn report_remote(sh_dir: &Shell, remote_branch_strategy: &BranchNameStrategy, refs: &[String], remote: &str) -> Result<usize, DriftCommandReportRemoteError> {
+        use DriftCommandReportRemoteError::*;
+        let TemplateConfig {
+            prefix,
+            branch,
+            path,
+            include,
+            exclude,
+            sync_mode,
+            managed,
+            ..
+        } = handle!(TemplateConfig::load(sh_dir, remote), TemplateConfigLoadFailed, remote);
+
+        // The scope limits the comparison to the files that the template owns, the filter separates the files that are excluded by the include/exclude config
+        let (base_commit, scope_pathspecs, filter_pathspecs) = match sync_mode {
+            SyncMode::Merge => {
+                let remote_branch_name = match branch {
+                    Some(branch) => branch,
+                    None => {
+                        let remote_prefix = format!("refs/remotes/{remote}");
+                        handle!(remote_branch_strategy.to_branch_name(&remote_prefix, refs), RemoteBranchNameResolveFailed, remote, prefix: remote_prefix)
+                    }
+                };
+                let remote_ref = format!("{remote}/{remote_branch_name}");
+                let merged_commit = match &path {
+                    Some(path) => handle!(MergeCommand::split_commit(sh_dir, &remote_ref, path), SplitCommitFailed, remote_ref, path: path.clone()),
+                    None => remote_ref,
+                };
+                let merge_base = handle!(
+                    cmd!(sh_dir, "git merge-base HEAD {merged_commit}")
+                        .ignore_status()
+                        .read(),
+                    GitMergeBaseFailed,
+                    merged_commit
+                );
+                if merge_base.is_empty() {
+                    eprintln!("[WARN] '{merged_commit}' has never been merged into HEAD");
+                    return Ok(0);
+                }
+                let filter_pathspecs = if include.is_empty() && exclude.is_empty() {
+                    vec![]
+                } else {
+                    Self::filter_pathspecs(&include, &exclude)
+                };
+                (merge_base, vec![], filter_pathspecs)
+            }
+            SyncMode::Files => {
+                let synced_commit = handle!(MergeCommand::synced_commit(sh_dir, remote), SyncedCommitFailed, remote);
+                let Some(synced_commit) = synced_commit else {
+                    eprintln!("[WARN] The managed files of '{remote}' have never been synced into HEAD");
+                    return Ok(0);
+                };
+                if managed.is_empty() {
+                    eprintln!("[WARN] Template '{remote}' doesn't declare any managed files");
+                    return Ok(0);
+                }
+                let scope_pathspecs = managed
+                    .iter()
+                    .map(|glob| format!(":(top,glob){glob}"))
+                    .collect_vec();
+                (synced_commit, scope_pathspecs, vec![])
+            }
+        };
+
+        // The files that exist only in the child repository are not drift, so only the modified and deleted files are listed
+        let child_tree = match &prefix {
+            Some(prefix) => format!("HEAD:{prefix}"),
+            None => "HEAD".to_string(),
+        };
+        let changed_paths = handle!(cmd!(sh_dir, "git diff --name-only --no-renames --diff-filter=MD {base_commit} {child_tree} -- {scope_pathspecs...}").read(), GitDiffFailed, base_commit);
+        let kept_paths = if filter_pathspecs.is_empty() {
+            changed_paths.clone()
+        } else {
+            handle!(cmd!(sh_dir, "git diff --name-only --no-renames --diff-filter=MD {base_commit} {child_tree} -- {filter_pathspecs...}").read(), GitDiffKeptPathsFailed, base_commit)
+        };
+        let to_child_path = |changed_path: &str| match &prefix {
+            Some(prefix) => format!("{prefix}/{changed_path}"),
+            None => changed_path.to_string(),
+        };
+        let (kept, filtered): (Vec<&str>, Vec<&str>) = changed_paths.lines().partition(|changed_path| {
+            kept_paths
+                .lines()
+                .any(|kept_path| kept_path == *changed_path)
+        });
+        filtered
+            .iter()
+            .for_each(|changed_path| println!("[FILTERED] {remote} {child_path}", child_path = to_child_path(changed_path)));
+        let child_paths = kept.into_iter().map(to_child_path).collect_vec();
+        if child_paths.is_empty() {
+            return Ok(0);
+        }
+
+        let child_paths_slice = child_paths.as_slice();
+        let attributes = handle!(cmd!(sh_dir, "git check-attr merge -- {child_paths_slice...}").read(), GitCheckAttrFailed);
+        let overridden_paths = attributes
+            .lines()
+            .filter_map(|line| line.strip_suffix(": merge: ours"))
+            .collect_vec();
+        let (overridden, drifted): (Vec<&String>, Vec<&String>) = child_paths
+            .iter()
+            .partition(|child_path| overridden_paths.contains(&child_path.as_str()));
+
+        overridden
+            .iter()
+            .for_each(|child_path| println!("[OVERRIDDEN] {remote} {child_path}"));
+        drifted
+            .iter()
+            .for_each(|child_path| println!("[DRIFT] {remote} {child_path}"));
+
+        Ok(drifted.len())
+    }
+
+    /// Returns the pathspecs that match the template paths kept by the `include` and `exclude` globs (relative to the template root)
+    fn filter_pathspecs(include: &[String], exclude: &[String]) -> Vec<String> {
+        let include_pathspecs = if include.is_empty() {
+            vec![":(top,glob)**".to_string()]
+        } else {
+            include
+                .iter()
+                .map(|glob| format!(":(top,glob){glob}"))
+                .collect()
+        };
+        let exclude_pathspecs = exclude
+            .iter()
+            .map(|glob| format!(":(top,exclude,glob){glob}"));
+        include_pathspecs
+            .into_iter()
+            .chain(exclude_pathspecs)
+            .collect()
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum DriftCommandRunError {
+    #[error("failed to resolve the target directory")]
+    UnwrapOrCurrentDirFailed { source: UnwrapOrCurrentDirError },
+    #[error("failed to create a shell instance")]
+    ShellNewFailed { source: xshell::Error },
+    #[error("failed to read git remote names")]
+    GitRemoteNamesFailed { source: GitRemoteNamesError },
+    #[error("failed to read git refs")]
+    GitRefsFailed { source: GitRefsError },
+    #[error("failed to report drift for {len} remotes", len = source.len())]
+    ReportRemotesFailed { source: ErrVec<DriftCommandReportRemoteError> },
+}
+
+#[derive(Error, Debug)]
+pub enum DriftCommandReportRemoteError {
+    #[error("failed to load the config of template '{remote}'")]
+    TemplateConfigLoadFailed { source: TemplateConfigLoadError, remote: String },
+    #[error("failed to resolve remote branch name for '{remote}' with prefix '{prefix}'")]
+    RemoteBranchNameResolveFailed { source: BranchNameStrategyToBranchNameError, remote: String, prefix: String },
+    #[error("failed to split the history of '{path}' from '{remote_ref}'")]
+    SplitCommitFailed { source: MergeCommandSplitCommitError, remote_ref: String, path: String },
+    #[error("failed to find the last merged revision of '{merged_commit}'")]
+    GitMergeBaseFailed { source: xshell::Error, merged_commit: String },
+    #[error("failed to read the synced commit of template '{remote}'")]
+    SyncedCommitFailed { source: MergeCommandSyncedCommitError, remote: String },
+    #[error("failed to compare HEAD with the template revision '{base_commit}'")]
+    GitDiffFailed { source: xshell::Error, base_commit: String },
+    #[error("failed to compare HEAD with the template revision '{base_commit}' within the include/exclude config")]
+    GitDiffKeptPathsFailed { source: xshell::Error, base_commit: String },
+    #[error("failed to read the merge attributes")]
+    GitCheckAttrFailed { source: xshell::Error },
+}
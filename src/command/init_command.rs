@@ -1,10 +1,14 @@
-use crate::{GitLocalBranchExists, GitLocalBranchExistsError, GitRemoteExistsError, SetExecutableBit, SetExecutableBitError, git_remote_exists};
+use crate::{BranchNameStrategy, GitLocalBranchExists, GitLocalBranchExistsError, GitRemoteExistsError, HookOptions, HookPhase, HookRunnerRunError, HookRunnerRunHookError, MergeCommand, MergeCommandMergeRemoteError, MergeCommandResolveMergedRefError, MergeContext, Provisioner, ProvisionerLoadError, TemplateConfig, TemplateConfigSaveError, TemplateRef, TemplateVariable, TemplateVariableLoadAllError, git_remote_exists};
 use clap::{Parser, value_parser};
-use errgonomic::handle;
-use std::path::PathBuf;
+use demand::Input;
+use errgonomic::{handle, handle_opt};
+use std::env::var;
+use std::fs::{read, write};
+use std::io;
+use std::path::{Path, PathBuf};
 use std::process::ExitCode;
+use std::str::from_utf8;
 use thiserror::Error;
-use url::Url;
 use xshell::{Shell, cmd};
 
 #[derive(Parser, Clone, Debug)]
@@ -17,25 +21,40 @@ pub struct InitCommand {
     #[arg(long, short, default_value = "main")]
     pub branch_name: String,
 
-    /// Don't run the post-init script
+    /// Don't run the post-init hooks
     #[arg(long, short)]
     pub skip_post_init: bool,
 
-    /// Additional post-init script to run after the template's post-init script
+    /// Additional post-init hook to run after the template's post-init hooks
     #[arg(long, value_parser = value_parser!(PathBuf))]
     pub post_init: Option<PathBuf>,
 
+    /// Subdirectory of the repository to attach the template to (e.g. "crates/foo"); the template files are merged into this subdirectory instead of the repository root
+    #[arg(long)]
+    pub prefix: Option<String>,
+
+    /// Value of a template variable (e.g. "name=my-project"); the variables without a value are read from `REPOCONF_VAR_<NAME>` or prompted for
+    #[arg(long = "var", value_name = "NAME=VALUE")]
+    pub vars: Vec<String>,
+
     /// Template repo name
     #[arg()]
     pub template_name: String,
 
-    /// Template repo URL
-    #[arg(value_parser = value_parser!(Url))]
-    pub template_url: Url,
+    /// Template repo URL, optionally followed by a branch and a subdirectory of the template repo (e.g. "https://github.com/example/templates#main:templates/rust-lib")
+    #[arg(value_parser = value_parser!(TemplateRef))]
+    pub template_url: TemplateRef,
 
     /// Directory to clone the new repository to
     #[arg(value_parser = value_parser!(PathBuf))]
     pub dir: PathBuf,
+
+    /// Additional environment variables for the hooks (set by the commands that delegate to `init`)
+    #[arg(skip)]
+    pub hook_env: Vec<(String, String)>,
+
+    #[command(flatten)]
+    pub hook_options: HookOptions,
 }
 
 impl InitCommand {
@@ -48,18 +67,46 @@ impl InitCommand {
             branch_name,
             skip_post_init,
             post_init,
+            prefix,
+            vars,
             dir,
+            hook_env,
+            hook_options,
         } = self;
 
         let sh_cwd = handle!(Shell::new(), ShellNewFailed);
 
         let remote_template_name = format!("repoconf-{template_name}");
-        let remote_template_url = template_url.as_str();
+        let TemplateRef {
+            url: template_repo_url,
+            branch: template_branch,
+            path: template_path,
+        } = template_url;
+        let remote_template_url = template_repo_url.as_str();
+
+        let remote_branch_name = template_branch
+            .clone()
+            .unwrap_or_else(|| branch_name.clone());
 
         let sh_dir = sh_cwd.with_current_dir(&dir);
+        let hook_runner = hook_options
+            .hook_runner()
+            .with_env("REPOCONF_COMMAND", "init")
+            .with_env("REPOCONF_NO_PUSH", "0")
+            .with_env("REPOCONF_REMOTE", &remote_template_name)
+            .with_env("REPOCONF_TEMPLATE_NAME", &template_name)
+            .with_env("REPOCONF_TEMPLATE_URL", remote_template_url)
+            .with_env("REPOCONF_TEMPLATE", remote_template_url)
+            .with_env("REPOCONF_LOCAL_BRANCH", &branch_name)
+            .with_env("REPOCONF_REMOTE_BRANCH", &remote_branch_name)
+            .with_template_url(remote_template_url);
+        let hook_runner = hook_env
+            .into_iter()
+            .fold(hook_runner, |hook_runner, (name, value)| hook_runner.with_env(name, value));
+        handle!(hook_runner.run(&sh_dir, HookPhase::PreInit), RunHooksFailed, phase: HookPhase::PreInit);
 
         let remote_exists = handle!(
-            git_remote_exists(&sh_dir, remote_template_url),
+            git_remote_exists(&sh_dir, &remote_template_name, remote_template_url),
             GitRemoteExistsFailed,
             remote_template_url: remote_template_url
         );
@@ -71,41 +118,172 @@ impl InitCommand {
                 remote_template_url: remote_template_url
             );
         }
+        let template_config = TemplateConfig {
+            prefix,
+            branch: template_branch,
+            path: template_path,
+            ..TemplateConfig::default()
+        };
+        handle!(template_config.save(&sh_dir, &remote_template_name), TemplateConfigSaveFailed, remote_template_name);
         handle!(cmd!(sh_dir, "git remote update {remote_template_name}").run_echo(), GitRemoteUpdateFailed, remote_template_name);
 
+        let TemplateConfig {
+            prefix,
+            path: template_path,
+            ..
+        } = template_config;
         let local_branch_exists = handle!(sh_dir.git_local_branch_exists(&branch_name), GitLocalBranchExistsFailed, branch_name);
-        if local_branch_exists {
-            handle!(cmd!(sh_dir, "git checkout {branch_name}").run_echo(), GitCheckoutFailed, branch_name);
-        } else {
-            handle!(cmd!(sh_dir, "git checkout -b {branch_name} {remote_template_name}/{branch_name}").run_echo(), GitCheckoutNewBranchFailed, branch_name, remote_template_name);
-            handle!(cmd!(sh_dir, "git branch --unset-upstream {branch_name}").run_echo(), GitBranchUnsetUpstreamFailed, branch_name);
+        match prefix {
+            None if local_branch_exists => {
+                handle!(cmd!(sh_dir, "git checkout {branch_name}").run_echo(), GitCheckoutFailed, branch_name);
+            }
+            None => {
+                let merged_ref = handle!(MergeCommand::resolve_merged_ref(&sh_dir, &remote_template_name, &remote_branch_name, template_path.as_deref()), ResolveMergedRefFailed, remote_template_name);
+                handle!(cmd!(sh_dir, "git checkout --no-track -b {branch_name} {merged_ref}").run_echo(), GitCheckoutNewBranchFailed, branch_name, merged_ref);
+            }
+            Some(_) => {
+                if local_branch_exists {
+                    handle!(cmd!(sh_dir, "git checkout {branch_name}").run_echo(), GitCheckoutFailed, branch_name);
+                } else {
+                    // The template history is attached to a subdirectory, so the local branch starts from an empty root commit instead of the template branch
+                    handle!(cmd!(sh_dir, "git checkout --orphan {branch_name}").run_echo(), GitCheckoutOrphanFailed, branch_name);
+                    handle!(cmd!(sh_dir, "git commit --allow-empty -m 'Initial commit'").run_echo(), GitCommitInitialFailed, branch_name);
+                }
+                let remote_branch_strategy = BranchNameStrategy::Exact(remote_branch_name);
+                let provisioner = handle!(Provisioner::load(&sh_dir), ProvisionerLoadFailed);
+                let context = MergeContext {
+                    hook_runner: &hook_runner,
+                    provisioner,
+                    remote_branch_strategy: &remote_branch_strategy,
+                    refs: &[],
+                    allow_unrelated_histories: false,
+                    overridable_remotes: &[],
+                };
+                handle!(MergeCommand::merge_remote(&sh_dir, context, &remote_template_name, &[]), MergeRemoteFailed, remote_template_name);
+            }
         }
 
+        let manifest = match &prefix {
+            Some(prefix) => PathBuf::from(prefix).join(TemplateVariable::MANIFEST_PATH),
+            None => PathBuf::from(TemplateVariable::MANIFEST_PATH),
+        };
+        let hook_runner = if sh_dir.path_exists(&manifest) {
+            let variables = handle!(TemplateVariable::load_all(&sh_dir, &manifest), TemplateVariableLoadAllFailed, manifest);
+            let values = handle!(Self::collect_variables(variables, &vars, hook_options.non_interactive), CollectVariablesFailed);
+            handle!(Self::render_variables(&sh_dir, &values, prefix.as_deref(), local_branch_exists), RenderVariablesFailed);
+            values
+                .into_iter()
+                .fold(hook_runner, |hook_runner, (name, value)| hook_runner.with_env(TemplateVariable::env_name(&name), value))
+        } else {
+            hook_runner
+        };
+
+        handle!(hook_runner.run(&sh_dir, HookPhase::PrePush), RunHooksFailed, phase: HookPhase::PrePush);
         handle!(cmd!(sh_dir, "git push --set-upstream {remote_name} {branch_name}").run_echo(), GitPushFailed, remote_name, branch_name);
 
         if !skip_post_init {
-            let post_init_script = sh_dir.current_dir().join(".repoconf/hooks/post-init.sh");
-            handle!(Self::run_post_init(&sh_dir, &post_init_script, &dir), RunPostInitFailed);
+            handle!(hook_runner.run(&sh_dir, HookPhase::PostInit), RunHooksFailed, phase: HookPhase::PostInit);
             if let Some(post_init) = post_init {
-                handle!(Self::run_post_init(&sh_dir, &post_init, &dir), RunPostInitFailed);
+                if sh_dir.path_exists(&post_init) {
+                    handle!(hook_runner.run_hook(&sh_dir, HookPhase::PostInit, &post_init), RunPostInitHookFailed, path: post_init);
+                } else {
+                    eprintln!("[WARN] Could not find post-init hook at {path}", path = post_init.display());
+                }
             }
         }
 
         Ok(ExitCode::SUCCESS)
     }
 
-    fn run_post_init(sh_dir: &Shell, path: &PathBuf, dir: &PathBuf) -> Result<(), InitCommandRunPostInitError> {
-        use InitCommandRunPostInitError::*;
-        if sh_dir.path_exists(path) {
-            handle!(path.set_executable_bit(), SetExecutableBitFailed, path: path);
-            handle!(
-                cmd!(sh_dir, "usage bash {path} {dir}").run_interactive(),
-                RunInteractiveFailed,
-                path: path,
-                dir: dir
-            );
-        } else {
-            eprintln!("[WARN] Could not find post-init script at {path}", path = path.display());
+    /// Returns the values of the template `variables`: from the `--var` flags, then from the environment, then from an interactive prompt (or from the defaults in the non-interactive mode)
+    fn collect_variables(variables: Vec<TemplateVariable>, vars: &[String], non_interactive: bool) -> Result<Vec<(String, String)>, InitCommandCollectVariablesError> {
+        use InitCommandCollectVariablesError::*;
+        let flag_values = vars
+            .iter()
+            .map(|flag| {
+                let (name, value) = handle_opt!(flag.split_once('='), VarInvalid, var: flag.as_str());
+                Ok((name, value))
+            })
+            .collect::<Result<Vec<(&str, &str)>, InitCommandCollectVariablesError>>()?;
+        variables
+            .into_iter()
+            .map(|variable| {
+                let TemplateVariable {
+                    name,
+                    description,
+                    default,
+                } = variable;
+                let env_name = TemplateVariable::env_name(&name);
+                let flag_value = flag_values
+                    .iter()
+                    .rev()
+                    .find(|(flag_name, _)| *flag_name == name)
+                    .map(|(_, value)| value.to_string());
+                let value = match flag_value.or_else(|| var(&env_name).ok()) {
+                    Some(value) => value,
+                    None if non_interactive => handle_opt!(default, VariableMissing, name, env_name),
+                    None => {
+                        let title = format!("Template variable '{name}'");
+                        let input = Input::new(&title)
+                            .description(description.as_deref().unwrap_or_default())
+                            .placeholder(default.as_deref().unwrap_or_default());
+                        let value = handle!(input.run(), InputRunFailed, name);
+                        if value.is_empty() { default.unwrap_or_default() } else { value }
+                    }
+                };
+                Ok((name, value))
+            })
+            .collect()
+    }
+
+    /// Replaces the placeholders of the template variables in the contents and the names of the tracked files under `prefix` and commits the result
+    ///
+    /// The files of an existing branch belong to the child repository, so only the files that come from the template are rendered: a new branch or the `prefix` subdirectory. The files that are not valid UTF-8 are renamed but their contents are left untouched.
+    fn render_variables(sh_dir: &Shell, values: &[(String, String)], prefix: Option<&str>, local_branch_exists: bool) -> Result<(), InitCommandRenderVariablesError> {
+        use InitCommandRenderVariablesError::*;
+        if local_branch_exists && prefix.is_none() {
+            return Ok(());
+        }
+        let render = |text: &str| {
+            values
+                .iter()
+                .fold(text.to_string(), |text, (name, value)| text.replace(&TemplateVariable::placeholder(name), value))
+        };
+        let pathspec = prefix.unwrap_or(".");
+        let paths = handle!(cmd!(sh_dir, "git ls-files -z -- {pathspec}").read(), GitLsFilesFailed);
+        paths
+            .split('\0')
+            .filter(|path| !path.is_empty())
+            .try_for_each(|path| {
+                let file_path = sh_dir.current_dir().join(path);
+                let contents = handle!(read(&file_path), ReadFailed, path);
+                if let Ok(contents) = from_utf8(&contents) {
+                    let rendered_contents = render(contents);
+                    if rendered_contents != contents {
+                        handle!(write(&file_path, rendered_contents), WriteFailed, path);
+                    }
+                }
+                let rendered_path = render(path);
+                if rendered_path != path {
+                    if let Some(parent) = Path::new(&rendered_path)
+                        .parent()
+                        .filter(|parent| !parent.as_os_str().is_empty())
+                    {
+                        handle!(sh_dir.create_dir(parent), CreateDirFailed, path: parent.to_string_lossy());
+                    }
+                    handle!(cmd!(sh_dir, "git mv -- {path} {rendered_path}").run_echo(), GitMvFailed, path, rendered_path);
+                }
+                Ok(())
+            })?;
+        handle!(cmd!(sh_dir, "git add --all -- {pathspec}").run_echo(), GitAddFailed);
+        let is_unchanged = handle!(
+            cmd!(sh_dir, "git diff --cached --quiet")
+                .to_command()
+                .status(),
+            GitDiffStatusFailed
+        );
+        if !is_unchanged.success() {
+            handle!(cmd!(sh_dir, "git commit -m 'Render template variables'").run_echo(), GitCommitFailed);
         }
         Ok(())
     }
@@ -125,20 +303,103 @@ pub enum InitCommandRunError {
     GitLocalBranchExistsFailed { source: GitLocalBranchExistsError, branch_name: String },
     #[error("failed to check out local branch '{branch_name}'")]
     GitCheckoutFailed { source: xshell::Error, branch_name: String },
-    #[error("failed to create local branch '{branch_name}' from '{remote_template_name}'")]
-    GitCheckoutNewBranchFailed { source: xshell::Error, branch_name: String, remote_template_name: String },
-    #[error("failed to unset upstream for branch '{branch_name}'")]
-    GitBranchUnsetUpstreamFailed { source: xshell::Error, branch_name: String },
+    #[error("failed to resolve the ref to merge from template remote '{remote_template_name}'")]
+    ResolveMergedRefFailed { source: MergeCommandResolveMergedRefError, remote_template_name: String },
+    #[error("failed to create local branch '{branch_name}' from '{merged_ref}'")]
+    GitCheckoutNewBranchFailed { source: xshell::Error, branch_name: String, merged_ref: String },
+    #[error("failed to save the config of template '{remote_template_name}'")]
+    TemplateConfigSaveFailed { source: TemplateConfigSaveError, remote_template_name: String },
+    #[error("failed to create orphan branch '{branch_name}'")]
+    GitCheckoutOrphanFailed { source: xshell::Error, branch_name: String },
+    #[error("failed to create the initial commit on branch '{branch_name}'")]
+    GitCommitInitialFailed { source: xshell::Error, branch_name: String },
+    #[error("failed to load the provisioner config")]
+    ProvisionerLoadFailed { source: ProvisionerLoadError },
+    #[error("failed to merge template remote '{remote_template_name}' into the subdirectory")]
+    MergeRemoteFailed { source: MergeCommandMergeRemoteError, remote_template_name: String },
+    #[error("failed to load the template variables from '{manifest}'")]
+    TemplateVariableLoadAllFailed { source: TemplateVariableLoadAllError, manifest: PathBuf },
+    #[error("failed to collect the values of the template variables")]
+    CollectVariablesFailed { source: InitCommandCollectVariablesError },
+    #[error("failed to render the template variables")]
+    RenderVariablesFailed { source: InitCommandRenderVariablesError },
     #[error("failed to push branch '{branch_name}' to remote '{remote_name}'")]
     GitPushFailed { source: xshell::Error, remote_name: String, branch_name: String },
-    #[error("failed to run a post-init script")]
-    RunPostInitFailed { source: InitCommandRunPostInitError },
+    #[error("failed to run the {phase} hooks")]
+    RunHooksFailed { source: HookRunnerRunError, phase: HookPhase },
+    #[error("failed to run post-init hook '{path}'")]
+    RunPostInitHookFailed { source: HookRunnerRunHookError, path: PathBuf },
+}
+
+#[derive(Error, Debug)]
+pub enum InitCommandCollectVariablesError {
+    #[error("invalid template variable '{var}' (expected NAME=VALUE)")]
+    VarInvalid { var: String },
+    #[error("template variable '{name}' has no value; pass it with `--var {name}=<value>` or set {env_name}")]
+    VariableMissing { name: String, env_name: String },
+    #[error("failed to prompt for template variable '{name}'")]
+    InputRunFailed { source: io::Error, name: String },
 }
 
 #[derive(Error, Debug)]
-pub enum InitCommandRunPostInitError {
-    #[error("failed to set executable bit for '{path}'")]
-    SetExecutableBitFailed { source: SetExecutableBitError, path: PathBuf },
-    #[error("failed to run post-init script '{path}' in '{dir}'")]
-    RunInteractiveFailed { source: xshell::Error, path: PathBuf, dir: PathBuf },
+pub enum InitCommandRenderVariablesError {
+    #[error("failed to list the tracked files")]
+    GitLsFilesFailed { source: xshell::Error },
+    #[error("failed to read '{path}'")]
+    ReadFailed { source: io::Error, path: String },
+    #[error("failed to write '{path}'")]
+    WriteFailed { source: io::Error, path: String },
+    #[error("failed to create directory '{path}'")]
+    CreateDirFailed { source: xshell::Error, path: String },
+    #[error("failed to rename '{path}' to '{rendered_path}'")]
+    GitMvFailed { source: xshell::Error, path: String, rendered_path: String },
+    #[error("failed to stage the rendered files")]
+    GitAddFailed { source: xshell::Error },
+    #[error("failed to check whether the rendered files have changed")]
+    GitDiffStatusFailed { source: io::Error },
+    #[error("failed to commit the rendered files")]
+    GitCommitFailed { source: xshell::Error },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{commit_test_file, new_test_repo};
+    use std::fs::read_to_string;
+
+    fn to_values(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn must_leave_an_existing_branch_unrendered() {
+        let sh = new_test_repo("init-existing-branch");
+        let commit = commit_test_file(&sh, "README.md", "# {{project}}\n");
+        InitCommand::render_variables(&sh, &to_values(&[("project", "demo")]), None, true).unwrap();
+        assert_eq!(read_to_string(sh.current_dir().join("README.md")).unwrap(), "# {{project}}\n");
+        assert_eq!(cmd!(sh, "git rev-parse HEAD").read().unwrap(), commit);
+    }
+
+    #[test]
+    fn must_render_only_the_prefix_of_an_existing_branch() {
+        let sh = new_test_repo("init-existing-branch-prefix");
+        commit_test_file(&sh, "README.md", "# {{project}}\n");
+        commit_test_file(&sh, "sub/{{project}}.md", "# {{project}}\n");
+        InitCommand::render_variables(&sh, &to_values(&[("project", "demo")]), Some("sub"), true).unwrap();
+        assert_eq!(read_to_string(sh.current_dir().join("README.md")).unwrap(), "# {{project}}\n");
+        assert_eq!(read_to_string(sh.current_dir().join("sub/demo.md")).unwrap(), "# demo\n");
+        assert!(!sh.path_exists("sub/{{project}}.md"));
+    }
+
+    #[test]
+    fn must_render_a_new_branch() {
+        let sh = new_test_repo("init-new-branch");
+        let commit = commit_test_file(&sh, "README.md", "# {{project}}\n");
+        InitCommand::render_variables(&sh, &to_values(&[("project", "demo")]), None, false).unwrap();
+        assert_eq!(read_to_string(sh.current_dir().join("README.md")).unwrap(), "# demo\n");
+        assert_ne!(cmd!(sh, "git rev-parse HEAD").read().unwrap(), commit);
+    }
 }
@@ -1,8 +1,7 @@
-use crate::{BranchNameStrategy, BranchNameStrategyToBranchNameError, GitLocalBranchExists, GitLocalBranchExistsError, GitRefsError, GitRemoteNames, GitRemoteNamesError, IsCleanRepo, IsCleanRepoError, UnwrapOrCurrentDirError, git_refs, unwrap_or_current_dir};
+use crate::{BranchNameStrategy, BranchNameStrategyToBranchNameError, GitConfigGetAll, GitConfigGetAllError, GitIsAncestor, GitIsAncestorError, GitLocalBranchExists, GitLocalBranchExistsError, GitRefsError, GitRemoteNames, GitRemoteNamesError, HookOptions, HookPhase, HookRunner, HookRunnerRunCommandError, HookRunnerRunError, HookRunnerWithTemplateError, HookRunnerWithTemplateHooksError, HookRunnerWithTemplateUrlsError, IndexEntry, IsCleanRepo, IsCleanRepoError, MergeContext, Provisioner, ProvisionerLoadError, ProvisionerProvisionError, REPOCONF_BACKUP_REF_PREFIX, REPOCONF_MERGE_BRANCH_REF, REPOCONF_POST_MERGE_REF, REPOCONF_PRE_MERGE_REF, REPOCONF_SPLIT_REF_PREFIX, RebaseBranchesMode, SyncMode, TemplateConfig, TemplateConfigLoadError, TemplateLayer, TemplateLock, TemplateLockPickedError, TemplateLockRemovePickedError, TouchedPath, UnixTimestampError, UnwrapOrCurrentDirError, VerifyFailureMode, git_refs, unix_timestamp, unwrap_or_current_dir};
 use clap::{Parser, value_parser};
-use errgonomic::{handle, handle_bool};
+use errgonomic::{ErrVec, handle, handle_bool, handle_iter};
 use itertools::Itertools;
-use serde_json::Value;
 use std::path::{Path, PathBuf};
 use std::process::ExitCode;
 use thiserror::Error;
@@ -21,6 +20,13 @@ pub struct MergeCommand {
     )]
     pub continue_merge: bool,
 
+    /// Abort an in-progress merge, reset the local branch to its pre-merge commit and switch back to the branch that was checked out before the merge
+    #[arg(
+        long,
+        conflicts_with_all = ["continue_merge", "allow_dirty", "skip_dirty", "allow_unrelated_histories", "no_push", "no_remote_update", "skip_post_merge", "local_branch_strategy", "remote_branch_strategy"]
+    )]
+    pub abort: bool,
+
     /// Run the command even if the repository has uncommitted changes
     #[arg(long)]
     pub allow_dirty: bool,
@@ -29,6 +35,12 @@ pub struct MergeCommand {
     #[arg(long, conflicts_with = "allow_dirty")]
     pub skip_dirty: bool,
 
+    /// Stash uncommitted changes (including untracked files) before merging and restore them after merging
+    ///
+    /// If the merge stops on conflicts, the changes stay stashed until you run the command again with `--continue --stash` (or `--abort --stash`). If the command fails for any other reason, the changes are restored. The command refuses to start if the stash of an earlier run hasn't been restored yet
+    #[arg(long, conflicts_with_all = ["allow_dirty", "skip_dirty", "worktree"])]
+    pub stash: bool,
+
     #[arg(long)]
     pub allow_unrelated_histories: bool,
 
@@ -44,13 +56,51 @@ pub struct MergeCommand {
     #[arg(long)]
     pub skip_post_merge: bool,
 
+    /// Shell command that verifies the merged tree after the post-merge hooks and before pushing (e.g. "mise run build && mise run test"; defaults to the `repoconf.verify` git config)
+    ///
+    /// If the command fails, the merge is not pushed and the local branch is reset to the pre-merge commit (see `--verify-failure-mode`)
+    #[arg(long)]
+    pub verify: Option<String>,
+
+    /// What to do with the merge if the verification fails
+    #[arg(value_enum, long, default_value_t)]
+    pub verify_failure_mode: VerifyFailureMode,
+
+    /// Run the template-owned hooks (`.repoconf/hooks`) as they exist at the merged template revisions instead of the copies in the child repository
+    ///
+    /// The child-owned hooks (`.repoconf/local/hooks`) are run from the child repository in both cases
+    #[arg(long)]
+    pub template_hooks: bool,
+
+    /// Update the local branches that match these patterns with the merge result (after pushing)
+    ///
+    /// The patterns are matched against the branch names (e.g. "feature/*"). The command stops at the first branch that can't be updated without conflicts
+    #[arg(long, num_args = 1.., conflicts_with = "worktree")]
+    pub rebase_branches: Vec<String>,
+
+    /// How to update the branches that match `--rebase-branches`
+    #[arg(value_enum, long, default_value_t)]
+    pub rebase_branches_mode: RebaseBranchesMode,
+
+    /// Stay on the local branch after merging instead of switching back to the branch (or commit) that was checked out before the merge
+    #[arg(long)]
+    pub stay: bool,
+
+    /// Merge in a temporary git worktree instead of the current checkout
+    ///
+    /// The merge doesn't touch the current checkout, so the repository may have uncommitted changes. If the local branch is checked out, it is fast-forwarded to the merge result after the merge is finished.
+    ///
+    /// If the merge stops on conflicts, resolve them in the worktree and run the command again with `--worktree --continue`
+    #[arg(long, conflicts_with_all = ["allow_dirty", "skip_dirty"])]
+    pub worktree: bool,
+
     /// Name of the local branch to merge onto
     ///
     /// If you pass "-", the command will determine the branch automatically: use "main" if exists, use "master" if exists.
     ///
     /// If the local branch doesn't exist, the command will exit with an error
     ///
-    /// The command will switch to this branch before merging
+    /// The command will switch to this branch before merging and switch back after merging (unless `--stay` is passed)
     #[arg(long = "local-branch", short = 'l', default_value = "-")]
     pub local_branch_strategy: BranchNameStrategy,
 
@@ -61,29 +111,111 @@ pub struct MergeCommand {
     /// Note that this is applied to all remotes
     #[arg(long = "remote-branch", short = 'r', default_value = "-")]
     pub remote_branch_strategy: BranchNameStrategy,
+
+    /// Unix timestamp that names the backup ref of the local branch (defaults to the current time)
+    ///
+    /// [`PropagateCommand`](crate::PropagateCommand) passes the same timestamp to every repository, so that all repositories of a single run can be rolled back to the same backup
+    #[arg(skip)]
+    pub backup_timestamp: Option<u64>,
+
+    #[command(flatten)]
+    pub hook_options: HookOptions,
 }
 
 impl MergeCommand {
+    /// A ref that points to the branch (symbolic ref) or the commit (regular ref) that was checked out before the merge
+    const PREVIOUS_HEAD_REF: &'static str = "refs/repoconf/previous-head";
+
+    /// A ref that points to the stash created by `--stash` until it is re-applied
+    const STASH_REF: &'static str = "refs/repoconf/stash";
+
     pub async fn run(self) -> Result<ExitCode, MergeCommandRunError> {
+        use MergeCommandRunError::*;
+        let stash = self.stash;
+        let dir = handle!(unwrap_or_current_dir(self.dir.clone()), UnwrapOrCurrentDirFailed);
+        let sh_dir = handle!(Shell::new(), ShellNewFailed).with_current_dir(&dir);
+        let result = self.merge(&sh_dir, dir);
+        match &result {
+            // The stash of an earlier run must not be re-applied because of an unrelated failure
+            Err(StashAlreadyExists {
+                ..
+            }) => {}
+            Err(_) if stash => {
+                if let Err(error) = Self::restore_stash(&sh_dir) {
+                    eprintln!("[WARN] Could not restore the stashed changes: {error}");
+                }
+            }
+            _ => {}
+        }
+        result
+    }
+
+    /// Runs the command in `sh_dir` (see [`Self::run`])
+    fn merge(self, sh_dir: &Shell, dir: PathBuf) -> Result<ExitCode, MergeCommandRunError> {
         use MergeCommandRunError::*;
         let Self {
-            dir,
+            dir: _,
             continue_merge,
+            abort,
             allow_dirty,
             skip_dirty,
+            stash,
             allow_unrelated_histories,
             no_push,
             no_remote_update,
             skip_post_merge,
+            template_hooks,
+            verify,
+            verify_failure_mode,
+            rebase_branches,
+            rebase_branches_mode,
+            stay,
+            worktree,
             local_branch_strategy,
             remote_branch_strategy,
+            backup_timestamp,
+            hook_options,
         } = self;
 
-        let dir = handle!(unwrap_or_current_dir(dir), UnwrapOrCurrentDirFailed);
-        let sh_dir = handle!(Shell::new(), ShellNewFailed).with_current_dir(&dir);
+        let worktree_dir = if worktree {
+            let worktree_dir = handle!(cmd!(sh_dir, "git rev-parse --path-format=absolute --git-path repoconf/worktree").read(), GitWorktreeDirReadFailed);
+            Some(PathBuf::from(worktree_dir))
+        } else {
+            None
+        };
+        let sh_merge = sh_dir.with_current_dir(worktree_dir.as_ref().unwrap_or(&dir));
+        let hook_runner = hook_options
+            .hook_runner()
+            .with_env("REPOCONF_COMMAND", "merge")
+            .with_env("REPOCONF_NO_PUSH", if no_push { "1" } else { "0" });
+
+        let provisioner = handle!(Provisioner::load(sh_dir), ProvisionerLoadFailed);
 
-        if continue_merge {
-            handle!(Self::continue_merge(&sh_dir), ContinueMergeFailed);
+        if abort {
+            handle!(Self::abort_merge(sh_dir, worktree_dir.as_deref()), AbortMergeFailed);
+            if stash {
+                handle!(Self::stash_pop(sh_dir), StashPopFailed);
+            }
+            return Ok(ExitCode::SUCCESS);
+        }
+
+        let hook_runner = if continue_merge {
+            let merge_branch_ref = REPOCONF_MERGE_BRANCH_REF;
+            let local_branch_name = handle!(cmd!(sh_dir, "git symbolic-ref --short {merge_branch_ref}").read(), GitMergeBranchReadFailed);
+            let remotes = handle!(sh_dir.git_remote_names(), GitRemoteNamesFailed)
+                .filter(|name| name.starts_with("repoconf"))
+                .collect_vec();
+            let hook_runner = handle!(hook_runner.with_template_urls(sh_dir, &remotes), WithTemplateUrlsFailed)
+                .with_env("REPOCONF_LOCAL_BRANCH", local_branch_name)
+                .with_env("REPOCONF_REMOTES", remotes.join(" "));
+            let hook_runner = if template_hooks {
+                let refs = handle!(git_refs(sh_dir), GitRefsFailed);
+                handle!(Self::with_template_hooks(&sh_merge, hook_runner, &remotes, &remote_branch_strategy, &refs), WithTemplateHooksFailed)
+            } else {
+                hook_runner
+            };
+            handle!(Self::continue_merge(&sh_merge, &hook_runner, provisioner), ContinueMergeFailed);
+            hook_runner
         } else {
             let remotes = handle!(sh_dir.git_remote_names(), GitRemoteNamesFailed)
                 .filter(|name| name.starts_with("repoconf"))
@@ -94,14 +226,31 @@ impl MergeCommand {
                 return Ok(ExitCode::SUCCESS);
             }
 
-            let is_clean = handle!(sh_dir.is_clean_repo(), IsCleanRepoFailed);
-            if skip_dirty && !is_clean {
-                eprintln!("[SKIP] repository '{}' has uncommitted changes", dir.display());
-                return Ok(ExitCode::SUCCESS);
+            if stash {
+                let stash_ref = Self::STASH_REF;
+                let stash_commit = handle!(
+                    cmd!(sh_dir, "git rev-parse --verify --quiet {stash_ref}")
+                        .ignore_status()
+                        .read(),
+                    GitStashRefReadFailed
+                );
+                handle_bool!(!stash_commit.is_empty(), StashAlreadyExists, stash_commit);
             }
-            handle_bool!(!allow_dirty && !is_clean, RepositoryNotClean, dir);
 
-            let refs = handle!(git_refs(&sh_dir), GitRefsFailed);
+            // The worktree mode doesn't touch the current checkout, so its uncommitted changes are irrelevant
+            let should_stash = if worktree_dir.is_none() {
+                let is_clean = handle!(sh_dir.is_clean_repo(), IsCleanRepoFailed);
+                if skip_dirty && !is_clean {
+                    eprintln!("[SKIP] repository '{}' has uncommitted changes", dir.display());
+                    return Ok(ExitCode::SUCCESS);
+                }
+                handle_bool!(!allow_dirty && !stash && !is_clean, RepositoryNotClean, dir);
+                stash && !is_clean
+            } else {
+                false
+            };
+
+            let refs = handle!(git_refs(sh_dir), GitRefsFailed);
 
             let local_branch_name = handle!(
                 local_branch_strategy.to_branch_name("refs/heads", &refs),
@@ -117,113 +266,1122 @@ impl MergeCommand {
             );
             handle_bool!(!local_branch_exists, LocalBranchDoesNotExist, branch_name: local_branch_name);
 
-            handle!(
-                cmd!(sh_dir, "git checkout {local_branch_name}").run_echo(),
-                GitCheckoutFailed,
-                branch_name: local_branch_name
-            );
+            // The stash is created only after the checks above, so that these checks can't leave it behind
+            if should_stash {
+                handle!(Self::stash_push(sh_dir), StashPushFailed);
+            }
+
+            let backup_timestamp = match backup_timestamp {
+                Some(backup_timestamp) => backup_timestamp,
+                None => handle!(unix_timestamp(), UnixTimestampFailed),
+            };
+            handle!(Self::record_merge_state(sh_dir, &local_branch_name, backup_timestamp), RecordMergeStateFailed);
+
+            match &worktree_dir {
+                Some(worktree_dir) => handle!(Self::add_worktree(sh_dir, worktree_dir, &local_branch_name), AddWorktreeFailed),
+                None => handle!(
+                    cmd!(sh_dir, "git checkout {local_branch_name}").run_echo(),
+                    GitCheckoutFailed,
+                    branch_name: local_branch_name
+                ),
+            }
 
             let remotes_slice = remotes.as_slice();
             if !no_remote_update {
                 handle!(cmd!(sh_dir, "git remote update {remotes_slice...}").run_echo(), GitRemoteUpdateFailed, remotes);
             }
 
-            handle!(Self::merge_remotes(&sh_dir, remotes, &remote_branch_strategy, &refs, allow_unrelated_histories), MergeRemotesFailed);
-        }
+            let hook_runner = handle!(hook_runner.with_template_urls(sh_dir, &remotes), WithTemplateUrlsFailed)
+                .with_env("REPOCONF_LOCAL_BRANCH", &local_branch_name)
+                .with_env("REPOCONF_REMOTES", remotes.join(" "));
+            let hook_runner = if template_hooks {
+                handle!(Self::with_template_hooks(&sh_merge, hook_runner, &remotes, &remote_branch_strategy, &refs), WithTemplateHooksFailed)
+            } else {
+                hook_runner
+            };
+            handle!(hook_runner.run(&sh_merge, HookPhase::PreMerge), RunHooksFailed, phase: HookPhase::PreMerge);
+            let context = MergeContext {
+                hook_runner: &hook_runner,
+                provisioner,
+                remote_branch_strategy: &remote_branch_strategy,
+                refs: &refs,
+                allow_unrelated_histories,
+                overridable_remotes: &[],
+            };
+            handle!(Self::merge_remotes(&sh_merge, context, remotes), MergeRemotesFailed);
+            hook_runner
+        };
+
+        let pre_merge_ref = REPOCONF_PRE_MERGE_REF;
+        let pre_merge_commit = handle!(cmd!(sh_dir, "git rev-parse {pre_merge_ref}").read(), GitPreMergeCommitReadFailed);
+        let merged_commit = handle!(cmd!(sh_merge, "git rev-parse HEAD").read(), GitMergedCommitReadFailed);
+        let hook_runner = hook_runner.with_env("REPOCONF_MERGED_RANGE", format!("{pre_merge_commit}..{merged_commit}"));
 
         if !skip_post_merge {
-            let post_merge_path = sh_dir.current_dir().join(".repoconf/hooks/post-merge.sh");
-            handle!(Self::run_post_merge(&sh_dir, post_merge_path), RunPostMergeFailed);
+            handle!(hook_runner.run(&sh_merge, HookPhase::PostMerge), RunHooksFailed, phase: HookPhase::PostMerge);
+        }
+
+        let verify = match verify {
+            Some(verify) => Some(verify),
+            None => handle!(sh_dir.git_config_get_all("repoconf.verify"), GitConfigGetAllFailed).pop(),
+        };
+        if let Some(verify) = verify {
+            let is_verified = handle!(Self::verify(&sh_merge, &hook_runner, &verify), VerifyFailed, verify);
+            if !is_verified {
+                let unverified_branch = handle!(Self::discard_unverified_merge(sh_dir, &sh_merge, worktree_dir.as_deref(), verify_failure_mode), DiscardUnverifiedMergeFailed);
+                match unverified_branch {
+                    Some(unverified_branch) => eprintln!("[UNVERIFIED] repository '{}' merged but failed verification; the merge is kept on branch '{unverified_branch}'", dir.display()),
+                    None => eprintln!("[UNVERIFIED] repository '{}' merged but failed verification; the merge has been discarded", dir.display()),
+                }
+                if worktree_dir.is_none() && !stay {
+                    handle!(Self::restore_previous_head(sh_dir), RestorePreviousHeadFailed);
+                }
+                if stash {
+                    handle!(Self::stash_pop(sh_dir), StashPopFailed);
+                }
+                return Ok(ExitCode::FAILURE);
+            }
+        }
+
+        if !no_push {
+            handle!(hook_runner.run(&sh_merge, HookPhase::PrePush), RunHooksFailed, phase: HookPhase::PrePush);
         }
 
+        handle!(Self::record_merge_result(sh_dir, &sh_merge), RecordMergeResultFailed);
+
+        let rebase_result = match &worktree_dir {
+            Some(worktree_dir) => {
+                handle!(Self::finish_worktree(sh_dir, &sh_merge, worktree_dir, no_push), FinishWorktreeFailed);
+                Ok(())
+            }
+            None => {
+                if !no_push {
+                    handle!(cmd!(sh_dir, "git push").run_echo(), GitPushFailed);
+                }
+                // `git for-each-ref` without patterns would list every branch
+                let rebase_result = if rebase_branches.is_empty() {
+                    Ok(())
+                } else {
+                    Self::rebase_branches(sh_dir, &rebase_branches, rebase_branches_mode)
+                };
+                if !stay {
+                    handle!(Self::restore_previous_head(sh_dir), RestorePreviousHeadFailed);
+                }
+                rebase_result
+            }
+        };
+
         if !no_push {
-            handle!(cmd!(sh_dir, "git push").run_echo(), GitPushFailed);
+            handle!(Self::forget_merge_state(sh_dir), ForgetMergeStateFailed);
+        }
+
+        if stash {
+            handle!(Self::stash_pop(sh_dir), StashPopFailed);
         }
 
+        // The merge has already been pushed, so a failed update of the other branches is reported only after the cleanup above
+        handle!(rebase_result, RebaseBranchesFailed, rebase_branches);
+
         Ok(ExitCode::SUCCESS)
     }
 
-    fn continue_merge(sh_dir: &Shell) -> Result<(), MergeCommandContinueMergeError> {
+    /// Updates the local branches that match `patterns` with the merge result, stops at the first branch that can't be updated cleanly, and switches back to the local branch
+    fn rebase_branches(sh_dir: &Shell, patterns: &[String], mode: RebaseBranchesMode) -> Result<(), MergeCommandRebaseBranchesError> {
+        use MergeCommandRebaseBranchesError::*;
+        let merge_branch_ref = REPOCONF_MERGE_BRANCH_REF;
+        let local_branch_name = handle!(cmd!(sh_dir, "git symbolic-ref --short {merge_branch_ref}").read(), GitMergeBranchReadFailed);
+        let branch_patterns = patterns
+            .iter()
+            .map(|pattern| format!("refs/heads/{pattern}"))
+            .collect_vec();
+        let branch_patterns_slice = branch_patterns.as_slice();
+        let branch_names = handle!(cmd!(sh_dir, "git for-each-ref --format='%(refname:short)' {branch_patterns_slice...}").read(), GitForEachRefFailed, branch_patterns);
+        let result = branch_names
+            .lines()
+            .filter(|branch_name| *branch_name != local_branch_name)
+            .try_fold(Vec::<String>::new(), |mut updated_branch_names, branch_name| match Self::rebase_branch(sh_dir, &local_branch_name, branch_name, mode) {
+                Ok(()) => {
+                    eprintln!("[UPDATED] branch '{branch_name}'");
+                    updated_branch_names.push(branch_name.to_string());
+                    Ok(updated_branch_names)
+                }
+                Err(source) => Err(RebaseBranchFailed {
+                    source,
+                    branch_name: branch_name.to_string(),
+                    updated_branch_names,
+                }),
+            });
+        handle!(cmd!(sh_dir, "git checkout {local_branch_name}").run_echo(), GitCheckoutFailed, local_branch_name);
+        result.map(|_| ())
+    }
+
+    /// Updates a single branch with the local branch; if the update stops on conflicts, aborts it, so that the branch is left as it was
+    fn rebase_branch(sh_dir: &Shell, local_branch_name: &str, branch_name: &str, mode: RebaseBranchesMode) -> Result<(), MergeCommandRebaseBranchError> {
+        use MergeCommandRebaseBranchError::*;
+        use RebaseBranchesMode::*;
+        match mode {
+            Rebase => {
+                if let Err(source) = cmd!(sh_dir, "git rebase {local_branch_name} {branch_name}").run_echo() {
+                    let rebase_merge_path = handle!(cmd!(sh_dir, "git rev-parse --path-format=absolute --git-path rebase-merge").read(), GitRebaseMergePathFailed);
+                    if sh_dir.path_exists(rebase_merge_path) {
+                        handle!(cmd!(sh_dir, "git rebase --abort").run_echo(), GitRebaseAbortFailed);
+                    }
+                    return Err(GitRebaseFailed {
+                        source,
+                    });
+                }
+            }
+            Merge => {
+                handle!(cmd!(sh_dir, "git checkout {branch_name}").run_echo(), GitCheckoutFailed);
+                if let Err(source) = cmd!(sh_dir, "git merge --no-edit {local_branch_name}").run_echo() {
+                    let merge_head_path = handle!(cmd!(sh_dir, "git rev-parse --path-format=absolute --git-path MERGE_HEAD").read(), GitMergeHeadPathFailed);
+                    if sh_dir.path_exists(merge_head_path) {
+                        handle!(cmd!(sh_dir, "git merge --abort").run_echo(), GitMergeAbortFailed);
+                    }
+                    return Err(GitMergeFailed {
+                        source,
+                    });
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Stashes uncommitted changes (including untracked files) and remembers the stash commit, so that [`Self::stash_pop`] restores exactly this stash
+    fn stash_push(sh_dir: &Shell) -> Result<(), MergeCommandStashPushError> {
+        use MergeCommandStashPushError::*;
+        handle!(cmd!(sh_dir, "git stash push --include-untracked --message repoconf-merge").run_echo(), GitStashPushFailed);
+        let stash_commit = handle!(cmd!(sh_dir, "git rev-parse refs/stash").read(), GitStashRevParseFailed);
+        let stash_ref = Self::STASH_REF;
+        handle!(cmd!(sh_dir, "git update-ref {stash_ref} {stash_commit}").run_echo(), GitUpdateRefFailed, stash_commit);
+        eprintln!("[INFO] Stashed uncommitted changes as {stash_commit}");
+        Ok(())
+    }
+
+    /// Re-applies the stash recorded by [`Self::stash_push`] after the command has failed, unless a merge is still in progress (in this case, the stash is re-applied by `--continue --stash` or `--abort --stash`)
+    ///
+    /// The stash has been created on the previously checked out branch, so this branch is checked out first. If it can't be checked out, the stash is kept.
+    fn restore_stash(sh_dir: &Shell) -> Result<(), MergeCommandRestoreStashError> {
+        use MergeCommandRestoreStashError::*;
+        let stash_ref = Self::STASH_REF;
+        let stash_commit = handle!(
+            cmd!(sh_dir, "git rev-parse --verify --quiet {stash_ref}")
+                .ignore_status()
+                .read(),
+            GitStashRefReadFailed
+        );
+        // The stash may have been re-applied already (e.g. if the command has failed after the cleanup)
+        if stash_commit.is_empty() {
+            return Ok(());
+        }
+        let merge_head_path = handle!(cmd!(sh_dir, "git rev-parse --path-format=absolute --git-path MERGE_HEAD").read(), GitMergeHeadPathFailed);
+        if sh_dir.path_exists(merge_head_path) {
+            eprintln!("[INFO] The uncommitted changes stay stashed until the merge is finished with `--continue --stash` or aborted with `--abort --stash`");
+            return Ok(());
+        }
+        if let Err(error) = Self::restore_previous_head(sh_dir) {
+            eprintln!("[WARN] Could not switch back to the previously checked out branch: {error}");
+            eprintln!("[INFO] The uncommitted changes stay stashed in '{stash_ref}'; re-apply them with `git stash apply {stash_ref}` on the right branch");
+            return Ok(());
+        }
+        handle!(Self::stash_pop(sh_dir), StashPopFailed);
+        Ok(())
+    }
+
+    /// Re-applies the stash recorded by [`Self::stash_push`] (if any).
+    ///
+    /// PRUNING: Drops the stash entry after it has been re-applied cleanly, because its changes are back in the working tree. If the stash can't be re-applied cleanly, it is kept, so the changes are never lost.
+    fn stash_pop(sh_dir: &Shell) -> Result<(), MergeCommandStashPopError> {
+        use MergeCommandStashPopError::*;
+        let stash_ref = Self::STASH_REF;
+        let stash_commit = handle!(
+            cmd!(sh_dir, "git rev-parse --verify --quiet {stash_ref}")
+                .ignore_status()
+                .read(),
+            GitStashRefReadFailed
+        );
+        if stash_commit.is_empty() {
+            return Ok(());
+        }
+        handle!(cmd!(sh_dir, "git stash apply {stash_commit}").run_echo(), GitStashApplyFailed, stash_commit);
+        let top_stash_commit = handle!(
+            cmd!(sh_dir, "git rev-parse --verify --quiet refs/stash")
+                .ignore_status()
+                .read(),
+            GitStashTopReadFailed
+        );
+        if top_stash_commit == stash_commit {
+            handle!(cmd!(sh_dir, "git stash drop").run_echo(), GitStashDropFailed, stash_commit);
+        } else {
+            eprintln!("[WARN] Stash {stash_commit} was re-applied but is no longer on top of the stash list, so it was not dropped");
+        }
+        handle!(cmd!(sh_dir, "git update-ref -d {stash_ref}").run_echo(), GitUpdateRefDeleteFailed);
+        Ok(())
+    }
+
+    /// Remembers the currently checked out branch (or commit, if HEAD is detached), the local branch and its current commit, so that the merge can be aborted or undone later
+    ///
+    /// Also records the current commit of the local branch as a backup ref that is kept until it is pruned with `repoconf backups prune`
+    pub fn record_merge_state(sh_dir: &Shell, local_branch_name: &str, backup_timestamp: u64) -> Result<(), MergeCommandRecordMergeStateError> {
+        use MergeCommandRecordMergeStateError::*;
+        let previous_head_ref = Self::PREVIOUS_HEAD_REF;
+        let current_ref = handle!(
+            cmd!(sh_dir, "git symbolic-ref --quiet HEAD")
+                .ignore_status()
+                .read(),
+            GitCurrentRefReadFailed
+        );
+        if current_ref.is_empty() {
+            let current_commit = handle!(cmd!(sh_dir, "git rev-parse HEAD").read(), GitCurrentCommitReadFailed);
+            handle!(cmd!(sh_dir, "git update-ref --no-deref {previous_head_ref} {current_commit}").run_echo(), GitUpdatePreviousHeadRefFailed, current_commit);
+        } else {
+            handle!(cmd!(sh_dir, "git symbolic-ref {previous_head_ref} {current_ref}").run_echo(), GitSymbolicPreviousHeadRefFailed, current_ref);
+        }
+        let local_branch_ref = format!("refs/heads/{local_branch_name}");
+        let merge_branch_ref = REPOCONF_MERGE_BRANCH_REF;
+        let pre_merge_ref = REPOCONF_PRE_MERGE_REF;
+        let post_merge_ref = REPOCONF_POST_MERGE_REF;
+        handle!(cmd!(sh_dir, "git update-ref -d {post_merge_ref}").run_echo(), GitUpdatePostMergeRefDeleteFailed);
+        handle!(cmd!(sh_dir, "git symbolic-ref {merge_branch_ref} {local_branch_ref}").run_echo(), GitSymbolicMergeBranchRefFailed, local_branch_ref);
+        handle!(cmd!(sh_dir, "git update-ref --no-deref {pre_merge_ref} {local_branch_ref}").run_echo(), GitUpdatePreMergeRefFailed, local_branch_ref);
+        let backup_ref = format!("{REPOCONF_BACKUP_REF_PREFIX}/{backup_timestamp}");
+        handle!(cmd!(sh_dir, "git update-ref --no-deref {backup_ref} {local_branch_ref}").run_echo(), GitUpdateBackupRefFailed, backup_ref, local_branch_ref);
+        Ok(())
+    }
+
+    /// Remembers the commit of the local branch after the merge (the current commit of `sh_merge`), so that [`UndoCommand`](crate::UndoCommand) undoes the merge only if the local branch still points to this commit
+    pub fn record_merge_result(sh_dir: &Shell, sh_merge: &Shell) -> Result<(), MergeCommandRecordMergeResultError> {
+        use MergeCommandRecordMergeResultError::*;
+        let post_merge_ref = REPOCONF_POST_MERGE_REF;
+        let merged_commit = handle!(cmd!(sh_merge, "git rev-parse HEAD").read(), GitRevParseHeadFailed);
+        handle!(cmd!(sh_dir, "git update-ref {post_merge_ref} {merged_commit}").run_echo(), GitUpdateRefFailed, merged_commit);
+        Ok(())
+    }
+
+    /// Forgets the commits recorded by [`Self::record_merge_state`] and [`Self::record_merge_result`].
+    ///
+    /// PRUNING: Removes the pre-merge and post-merge refs after the merge has been pushed, because a pushed merge can't be undone. The backup ref of the local branch is kept.
+    pub fn forget_merge_state(sh_dir: &Shell) -> Result<(), MergeCommandForgetMergeStateError> {
+        use MergeCommandForgetMergeStateError::*;
+        let pre_merge_ref = REPOCONF_PRE_MERGE_REF;
+        let post_merge_ref = REPOCONF_POST_MERGE_REF;
+        handle!(cmd!(sh_dir, "git update-ref -d {pre_merge_ref}").run_echo(), GitUpdateRefDeleteFailed, git_ref: pre_merge_ref);
+        handle!(cmd!(sh_dir, "git update-ref -d {post_merge_ref}").run_echo(), GitUpdateRefDeleteFailed, git_ref: post_merge_ref);
+        Ok(())
+    }
+
+    /// Aborts the merge recorded by [`Self::record_merge_state`] if it is in progress or if the local branch still points to its result (see [`Self::record_merge_result`]).
+    ///
+    /// PRUNING: Discards the in-progress merge (including conflict resolutions) and the commits of the remotes that have already been merged in this run, because the user has requested to abort the merge. The discarded commits remain reachable via the reflog.
+    fn abort_merge(sh_dir: &Shell, worktree_dir: Option<&Path>) -> Result<(), MergeCommandAbortMergeError> {
+        use MergeCommandAbortMergeError::*;
+        let pre_merge_ref = REPOCONF_PRE_MERGE_REF;
+        match worktree_dir {
+            // The worktree mode doesn't move the local branch until the merge is finished, so removing the worktree is enough
+            Some(worktree_dir) => handle!(cmd!(sh_dir, "git worktree remove --force {worktree_dir}").run_echo(), GitWorktreeRemoveFailed, worktree_dir),
+            None => {
+                let merge_branch_ref = REPOCONF_MERGE_BRANCH_REF;
+                let local_branch_ref = handle!(cmd!(sh_dir, "git symbolic-ref {merge_branch_ref}").read(), GitMergeBranchReadFailed);
+                let current_ref = handle!(
+                    cmd!(sh_dir, "git symbolic-ref --quiet HEAD")
+                        .ignore_status()
+                        .read(),
+                    GitCurrentRefReadFailed
+                );
+                handle_bool!(current_ref != local_branch_ref, NotOnMergeBranch, local_branch_ref, current_ref);
+                let merge_head_path = handle!(cmd!(sh_dir, "git rev-parse --path-format=absolute --git-path MERGE_HEAD").read(), GitMergeHeadPathFailed);
+                if sh_dir.path_exists(merge_head_path) {
+                    handle!(cmd!(sh_dir, "git merge --abort").run_echo(), GitMergeAbortFailed);
+                } else {
+                    // The pre-merge ref outlives a `--no-push` merge, so the reset is allowed only if the branch hasn't moved since the merge (like in `undo`)
+                    let post_merge_ref = REPOCONF_POST_MERGE_REF;
+                    let post_merge_commit = handle!(
+                        cmd!(sh_dir, "git rev-parse --verify --quiet {post_merge_ref}")
+                            .ignore_status()
+                            .read(),
+                        GitPostMergeCommitReadFailed
+                    );
+                    let current_commit = handle!(cmd!(sh_dir, "git rev-parse HEAD").read(), GitCurrentCommitReadFailed);
+                    handle_bool!(post_merge_commit != current_commit, MergeNotInProgress, local_branch_ref);
+                }
+                handle!(cmd!(sh_dir, "git reset --keep {pre_merge_ref}").run_echo(), GitResetFailed, local_branch_ref);
+                handle!(Self::restore_previous_head(sh_dir), RestorePreviousHeadFailed);
+            }
+        }
+        handle!(Self::forget_merge_state(sh_dir), ForgetMergeStateFailed);
+        Ok(())
+    }
+
+    /// Checks out the branch (or commit) recorded by [`Self::record_merge_state`]
+    pub fn restore_previous_head(sh_dir: &Shell) -> Result<(), MergeCommandRestorePreviousHeadError> {
+        use MergeCommandRestorePreviousHeadError::*;
+        let previous_head_ref = Self::PREVIOUS_HEAD_REF;
+        let previous_branch_name = handle!(
+            cmd!(sh_dir, "git symbolic-ref --quiet --short {previous_head_ref}")
+                .ignore_status()
+                .read(),
+            GitPreviousBranchReadFailed
+        );
+        if previous_branch_name.is_empty() {
+            let previous_commit = handle!(
+                cmd!(sh_dir, "git rev-parse --verify --quiet {previous_head_ref}")
+                    .ignore_status()
+                    .read(),
+                GitPreviousCommitReadFailed
+            );
+            if previous_commit.is_empty() {
+                eprintln!("[WARN] Could not find the previously checked out branch");
+            } else {
+                handle!(cmd!(sh_dir, "git checkout --detach {previous_commit}").run_echo(), GitCheckoutCommitFailed, previous_commit);
+            }
+        } else {
+            handle!(cmd!(sh_dir, "git checkout {previous_branch_name}").run_echo(), GitCheckoutBranchFailed, previous_branch_name);
+        }
+        Ok(())
+    }
+
+    /// Creates a detached worktree at the tip of the local branch (a regular worktree can't check out a branch that is already checked out in the current checkout)
+    fn add_worktree(sh_dir: &Shell, worktree_dir: &Path, local_branch_name: &str) -> Result<(), MergeCommandAddWorktreeError> {
+        use MergeCommandAddWorktreeError::*;
+        handle!(cmd!(sh_dir, "git worktree add --detach {worktree_dir} {local_branch_name}").run_echo(), GitWorktreeAddFailed, worktree_dir, local_branch_name);
+        Ok(())
+    }
+
+    /// Moves the local branch to the merge result, pushes the merge result to the upstream of the local branch, and removes the worktree.
+    ///
+    /// If the local branch is checked out in the current checkout, it is fast-forwarded with `git merge --ff-only` (which keeps the uncommitted changes that don't touch the merged paths). The fast-forward happens before pushing, so the pushed commit is always contained in the local branch.
+    ///
+    /// PRUNING: Removes the temporary worktree because the merge result has already been recorded in the local branch. `git worktree remove` refuses to remove a worktree with uncommitted changes, so the changes made by the post-merge hook are never lost.
+    fn finish_worktree(sh_dir: &Shell, sh_worktree: &Shell, worktree_dir: &Path, no_push: bool) -> Result<(), MergeCommandFinishWorktreeError> {
+        use MergeCommandFinishWorktreeError::*;
+        let merge_branch_ref = REPOCONF_MERGE_BRANCH_REF;
+        let local_branch_name = handle!(cmd!(sh_dir, "git symbolic-ref --short {merge_branch_ref}").read(), GitSymbolicRefReadFailed);
+        let local_branch_ref = format!("refs/heads/{local_branch_name}");
+        let merged_commit = handle!(cmd!(sh_worktree, "git rev-parse HEAD").read(), GitRevParseHeadFailed, worktree_dir);
+
+        let current_ref = handle!(
+            cmd!(sh_dir, "git symbolic-ref --quiet HEAD")
+                .ignore_status()
+                .read(),
+            GitCurrentRefReadFailed
+        );
+        if current_ref == local_branch_ref {
+            handle!(cmd!(sh_dir, "git merge --ff-only {merged_commit}").run_echo(), GitMergeFfOnlyFailed, local_branch_name, merged_commit);
+        } else {
+            handle!(cmd!(sh_dir, "git update-ref {local_branch_ref} {merged_commit}").run_echo(), GitUpdateRefFailed, local_branch_ref, merged_commit);
+        }
+
+        if !no_push {
+            let upstream_remote = handle!(cmd!(sh_dir, "git config --get branch.{local_branch_name}.remote").read(), GitUpstreamRemoteReadFailed, local_branch_name);
+            let upstream_ref = handle!(cmd!(sh_dir, "git config --get branch.{local_branch_name}.merge").read(), GitUpstreamRefReadFailed, local_branch_name);
+            handle!(cmd!(sh_dir, "git push {upstream_remote} {merged_commit}:{upstream_ref}").run_echo(), GitPushFailed, upstream_remote, upstream_ref);
+        }
+
+        handle!(cmd!(sh_dir, "git worktree remove {worktree_dir}").run_echo(), GitWorktreeRemoveFailed, worktree_dir);
+        Ok(())
+    }
+
+    /// Runs the `verify` shell command in the merged tree and returns true if it succeeds
+    ///
+    /// The command is run like a hook (see [`HookRunner::run_command`]), so it is subject to the same timeout, stdin and output capture
+    fn verify(sh_dir: &Shell, hook_runner: &HookRunner, verify: &str) -> Result<bool, MergeCommandVerifyError> {
+        use MergeCommandVerifyError::*;
+        eprintln!("[VERIFY] {verify}");
+        match hook_runner.run_command(sh_dir, cmd!(sh_dir, "bash -c {verify}")) {
+            Ok(()) => Ok(true),
+            Err(
+                error @ (HookRunnerRunCommandError::CommandFailed {
+                    ..
+                }
+                | HookRunnerRunCommandError::CommandTimedOut {
+                    ..
+                }),
+            ) => {
+                eprintln!("[VERIFY] {error}");
+                Ok(false)
+            }
+            Err(source) => Err(RunCommandFailed {
+                source,
+            }),
+        }
+    }
+
+    /// Resets the local branch to the pre-merge commit after a failed verification and returns the branch that keeps the merge (if any)
+    ///
+    /// PRUNING: Discards the merge from the local branch, because the merged tree has failed verification. The merge remains reachable via the reflog or via the kept branch in the [`VerifyFailureMode::Branch`] mode.
+    fn discard_unverified_merge(sh_dir: &Shell, sh_merge: &Shell, worktree_dir: Option<&Path>, mode: VerifyFailureMode) -> Result<Option<String>, MergeCommandDiscardUnverifiedMergeError> {
+        use MergeCommandDiscardUnverifiedMergeError::*;
+        let merge_branch_ref = REPOCONF_MERGE_BRANCH_REF;
+        let pre_merge_ref = REPOCONF_PRE_MERGE_REF;
+        let local_branch_name = handle!(cmd!(sh_dir, "git symbolic-ref --short {merge_branch_ref}").read(), GitMergeBranchReadFailed);
+        let unverified_branch = match mode {
+            VerifyFailureMode::Reset => None,
+            VerifyFailureMode::Branch => {
+                let unverified_branch = format!("repoconf/unverified/{local_branch_name}");
+                let merged_commit = handle!(cmd!(sh_merge, "git rev-parse HEAD").read(), GitRevParseHeadFailed);
+                handle!(cmd!(sh_dir, "git branch --force {unverified_branch} {merged_commit}").run_echo(), GitBranchFailed, unverified_branch);
+                Some(unverified_branch)
+            }
+        };
+        match worktree_dir {
+            // The worktree mode doesn't move the local branch until the merge is finished, so removing the worktree is enough
+            Some(worktree_dir) => handle!(cmd!(sh_dir, "git worktree remove --force {worktree_dir}").run_echo(), GitWorktreeRemoveFailed, worktree_dir),
+            None => handle!(cmd!(sh_dir, "git reset --keep {pre_merge_ref}").run_echo(), GitResetFailed, local_branch_name),
+        }
+        Ok(unverified_branch)
+    }
+
+    fn continue_merge(sh_dir: &Shell, hook_runner: &HookRunner, provisioner: Provisioner) -> Result<(), MergeCommandContinueMergeError> {
         use MergeCommandContinueMergeError::*;
         let merge_head_path = handle!(cmd!(sh_dir, "git rev-parse --path-format=absolute --git-path MERGE_HEAD").read(), GitMergeHeadPathFailed);
         handle_bool!(!sh_dir.path_exists(merge_head_path), MergeNotInProgress);
         let unmerged_paths = handle!(cmd!(sh_dir, "git diff --name-only --diff-filter=U").read(), UnmergedPathsReadFailed);
         handle_bool!(!unmerged_paths.is_empty(), UnresolvedConflicts, paths: unmerged_paths);
-        handle!(Self::install_mise_if_repository_configured(sh_dir), InstallMiseIfRepositoryConfiguredFailed);
+        handle!(provisioner.provision(sh_dir), ProvisionFailed);
+        handle!(hook_runner.run(sh_dir, HookPhase::PreMergeCommit), RunPreMergeCommitHooksFailed);
         handle!(cmd!(sh_dir, "git commit --no-edit").run_echo(), GitCommitFailed);
         Ok(())
     }
 
-    fn install_mise_if_repository_configured(sh_dir: &Shell) -> Result<(), MergeCommandInstallMiseIfRepositoryConfiguredError> {
-        use MergeCommandInstallMiseIfRepositoryConfiguredError::*;
-        let repository_root = PathBuf::from(handle!(cmd!(sh_dir, "git rev-parse --path-format=absolute --show-toplevel").read(), GitRepositoryRootReadFailed));
-        let mise_configs_json = handle!(cmd!(sh_dir, "mise --no-hooks config ls --json").read(), MiseConfigListFailed);
-        let mise_configs = handle!(serde_json::from_str::<Vec<Value>>(&mise_configs_json), FromStrFailed, json: mise_configs_json);
-        handle_bool!(
-            mise_configs.iter().any(|config| config.get("path").and_then(Value::as_str).is_none()),
-            MiseConfigListInvalid,
-            configs: mise_configs
-        );
-        let has_repository_mise_config = mise_configs
+    /// Switches the `hook_runner` to the template-owned hooks at the template revisions that are merged from the `remotes` (in the merge order)
+    fn with_template_hooks(sh_dir: &Shell, hook_runner: HookRunner, remotes: &[String], remote_branch_strategy: &BranchNameStrategy, refs: &[String]) -> Result<HookRunner, MergeCommandWithTemplateHooksError> {
+        use MergeCommandWithTemplateHooksError::*;
+        let layers = handle!(Self::order_remotes(sh_dir, remotes.to_vec()), OrderRemotesFailed);
+        layers.iter().try_fold(hook_runner, |hook_runner, layer| {
+            let remote = layer.remote.as_str();
+            let TemplateConfig {
+                branch,
+                path,
+                ..
+            } = handle!(TemplateConfig::load(sh_dir, remote), TemplateConfigLoadFailed, remote);
+            let remote_branch_name = match branch {
+                Some(branch) => branch,
+                None => {
+                    let remote_prefix = format!("refs/remotes/{remote}");
+                    handle!(remote_branch_strategy.to_branch_name(&remote_prefix, refs), RemoteBranchNameResolveFailed, remote, prefix: remote_prefix)
+                }
+            };
+            let merged_ref = handle!(Self::resolve_merged_ref(sh_dir, remote, &remote_branch_name, path.as_deref()), ResolveMergedRefFailed, remote);
+            let hook_runner = handle!(hook_runner.with_template_hooks(sh_dir, remote, &merged_ref), WithTemplateHooksFailed, remote, merged_ref);
+            Ok(hook_runner)
+        })
+    }
+
+    fn merge_remotes(sh_dir: &Shell, context: MergeContext, remotes: Vec<String>) -> Result<(), MergeCommandMergeRemotesError> {
+        use MergeCommandMergeRemotesError::*;
+        let layers = handle!(Self::order_remotes(sh_dir, remotes), OrderRemotesFailed);
+        layers
             .iter()
-            .filter_map(|config| config.get("path").and_then(Value::as_str))
-            .any(|path| Path::new(path).starts_with(&repository_root));
-        if has_repository_mise_config {
-            handle!(cmd!(sh_dir, "mise install").run_interactive(), MiseInstallFailed);
-        }
+            .try_fold(Vec::<TouchedPath>::new(), |touched_paths, layer| {
+                let TemplateLayer {
+                    remote,
+                    ancestors,
+                    priority,
+                } = layer;
+                let overridable_remotes = layers
+                    .iter()
+                    .filter(|other| ancestors.contains(&other.remote) || other.priority < *priority)
+                    .map(|other| other.remote.clone())
+                    .collect_vec();
+                let changed_paths = handle!(
+                    Self::merge_remote(
+                        sh_dir,
+                        MergeContext {
+                            overridable_remotes: &overridable_remotes,
+                            ..context
+                        },
+                        remote,
+                        &touched_paths
+                    ),
+                    MergeRemoteFailed,
+                    remote: remote.as_str()
+                );
+                let touched_paths = touched_paths
+                    .into_iter()
+                    .chain(changed_paths.into_iter().map(|path| TouchedPath {
+                        path,
+                        remote: remote.clone(),
+                    }))
+                    .collect_vec();
+                Ok(touched_paths)
+            })?;
         Ok(())
     }
 
-    fn merge_remotes(sh_dir: &Shell, remotes: Vec<String>, remote_branch_strategy: &BranchNameStrategy, refs: &[String], allow_unrelated_histories: bool) -> Result<(), MergeCommandMergeRemotesError> {
-        use MergeCommandMergeRemotesError::*;
-        remotes.into_iter().try_for_each(|remote| {
-            let remote_name = remote;
-            handle!(
-                Self::merge_remote(sh_dir, remote_branch_strategy, refs, allow_unrelated_histories, &remote_name),
-                MergeRemoteFailed,
-                remote: remote_name
-            );
-            Ok(())
+    /// Orders the template remotes by their depth in the inheritance chain (the number of ancestors, see [`TemplateConfig::extends`]), then by priority (see [`TemplateConfig::priority`]), then by name
+    ///
+    /// Every template is merged after the templates it extends and after the templates of the same depth with a lower priority, so the derived templates take precedence over their ancestors and the templates with a higher priority take precedence at the same depth. A template with a higher priority but fewer ancestors is merged earlier, so its conflicts with a deeper template of a lower priority are reported as conflicts between templates.
+    pub fn order_remotes(sh_dir: &Shell, remotes: Vec<String>) -> Result<Vec<TemplateLayer>, MergeCommandOrderRemotesError> {
+        use MergeCommandOrderRemotesError::*;
+        let configs = handle_iter!(
+            remotes
+                .iter()
+                .map(|remote| TemplateConfig::load(sh_dir, remote).map(|config| (remote.clone(), config))),
+            TemplateConfigLoadFailed
+        );
+        let parents = configs
+            .iter()
+            .map(|(remote, config)| {
+                let extends = config
+                    .extends
+                    .iter()
+                    .filter(|extended| remotes.contains(extended))
+                    .cloned()
+                    .collect_vec();
+                (remote.clone(), extends)
+            })
+            .collect_vec();
+        let layers = handle_iter!(
+            configs
+                .iter()
+                .map(|(remote, config)| Self::template_layer(&parents, remote, config.priority)),
+            TemplateLayerFailed
+        );
+        let layers = layers
+            .into_iter()
+            .sorted_by(|a, b| {
+                a.ancestors
+                    .len()
+                    .cmp(&b.ancestors.len())
+                    .then_with(|| a.priority.cmp(&b.priority))
+                    .then_with(|| a.remote.cmp(&b.remote))
+            })
+            .collect_vec();
+        Ok(layers)
+    }
+
+    /// Collects the transitive ancestors of `remote` from the `parents` list (pairs of a remote and the remotes it extends)
+    fn template_layer(parents: &[(String, Vec<String>)], remote: &str, priority: i64) -> Result<TemplateLayer, MergeCommandTemplateLayerError> {
+        use MergeCommandTemplateLayerError::*;
+        let parents_of = |child: &str| {
+            parents
+                .iter()
+                .filter(|(parent_child, _)| parent_child == child)
+                .flat_map(|(_, extends)| extends.iter().cloned())
+                .collect_vec()
+        };
+        // Every iteration adds the next generation of ancestors, so the number of remotes is an upper bound on the number of iterations
+        let ancestors = parents.iter().fold(parents_of(remote), |ancestors, _| {
+            let next_generation = ancestors
+                .iter()
+                .flat_map(|ancestor| parents_of(ancestor))
+                .filter(|ancestor| !ancestors.contains(ancestor))
+                .unique()
+                .collect_vec();
+            ancestors.into_iter().chain(next_generation).collect_vec()
+        });
+        handle_bool!(ancestors.iter().any(|ancestor| ancestor == remote), CycleDetected, remote, ancestors);
+        Ok(TemplateLayer {
+            remote: remote.to_string(),
+            ancestors,
+            priority,
         })
     }
 
-    fn merge_remote(sh_dir: &Shell, remote_branch_strategy: &BranchNameStrategy, refs: &[String], allow_unrelated_histories: bool, remote: &str) -> Result<(), MergeCommandMergeRemoteError> {
+    /// Merges the template `remote` into the current branch and returns the paths changed by the template (excluding the changes made by the hooks)
+    ///
+    /// The conflicts on the `touched_paths` that have been modified by the [`MergeContext::overridable_remotes`] earlier in the same run are resolved in favor of `remote`, the other conflicts on the `touched_paths` are reported as conflicts between templates
+    pub fn merge_remote(sh_dir: &Shell, context: MergeContext, remote: &str, touched_paths: &[TouchedPath]) -> Result<Vec<String>, MergeCommandMergeRemoteError> {
         use MergeCommandMergeRemoteError::*;
+        let MergeContext {
+            hook_runner,
+            provisioner,
+            remote_branch_strategy,
+            refs,
+            allow_unrelated_histories,
+            overridable_remotes,
+        } = context;
         let remote = remote.to_string();
-        let remote_prefix = format!("refs/remotes/{remote}");
-        let remote_branch_name = handle!(
-            remote_branch_strategy.to_branch_name(&remote_prefix, refs),
-            RemoteBranchNameResolveFailed,
-            prefix: remote_prefix,
-            remote
-        );
+        let pre_merge_commit = handle!(cmd!(sh_dir, "git rev-parse HEAD").read(), GitPreMergeCommitReadFailed, remote);
+        let TemplateConfig {
+            prefix,
+            branch,
+            path,
+            include,
+            exclude,
+            sync_mode,
+            managed,
+            ..
+        } = handle!(TemplateConfig::load(sh_dir, &remote), TemplateConfigLoadFailed, remote);
+
+        let remote_branch_name = match branch {
+            Some(branch) => branch,
+            None => {
+                let remote_prefix = format!("refs/remotes/{remote}");
+                handle!(
+                    remote_branch_strategy.to_branch_name(&remote_prefix, refs),
+                    RemoteBranchNameResolveFailed,
+                    prefix: remote_prefix,
+                    remote
+                )
+            }
+        };
+
+        let merged_ref = handle!(Self::resolve_merged_ref(sh_dir, &remote, &remote_branch_name, path.as_deref()), ResolveMergedRefFailed, remote, remote_branch_name);
+        let hook_runner = handle!(hook_runner.clone().with_template(sh_dir, &remote), HookRunnerWithTemplateFailed, remote, remote_branch_name)
+            .with_env("REPOCONF_REMOTE_BRANCH", &remote_branch_name)
+            .with_env("REPOCONF_MERGED_RANGE", format!("HEAD..{merged_ref}"));
+
+        if sync_mode == SyncMode::Files {
+            let context = MergeContext {
+                hook_runner: &hook_runner,
+                ..context
+            };
+            let synced_paths = handle!(Self::sync_files(sh_dir, context, &remote, &merged_ref, prefix.as_deref(), &managed), SyncFilesFailed, remote, remote_branch_name);
+            Self::report_overlaps(&remote, &synced_paths, touched_paths, &[]);
+            return Ok(synced_paths);
+        }
 
         // Use `git merge --no-commit` + `git commit --no-edit` to trigger a pre-commit hook
         // Note that pre-merge-commit hook can't add files to the current git index, which means it can't update generated files (e.g. AGENTS.md or README.md)
 
-        let flags = if allow_unrelated_histories {
-            vec!["--allow-unrelated-histories", "--no-commit"]
+        let is_filtered = !include.is_empty() || !exclude.is_empty();
+        let merge_result = Self::merge_template(sh_dir, &merged_ref, prefix.as_deref(), allow_unrelated_histories, is_filtered);
+
+        let merge_head_path = handle!(cmd!(sh_dir, "git rev-parse --path-format=absolute --git-path MERGE_HEAD").read(), GitMergeHeadPathFailed, remote, remote_branch_name);
+        let is_merging = sh_dir.path_exists(&merge_head_path);
+
+        if is_filtered && is_merging {
+            let filtered_paths = handle!(Self::filter_paths(sh_dir, prefix.as_deref(), &include, &exclude), FilterPathsFailed, remote, remote_branch_name);
+            filtered_paths
+                .iter()
+                .for_each(|filtered_path| eprintln!("[FILTERED] '{filtered_path}' is excluded by the include/exclude config of '{remote}', so the local version was kept"));
+        }
+
+        let conflicted_paths = if merge_result.is_err() && is_merging && !touched_paths.is_empty() {
+            let unmerged_paths = handle!(cmd!(sh_dir, "git diff --name-only --diff-filter=U").read(), GitUnmergedPathsReadFailed, remote, remote_branch_name);
+            let template_conflicts = unmerged_paths
+                .lines()
+                .filter_map(|unmerged_path| {
+                    touched_paths
+                        .iter()
+                        .rev()
+                        .find(|touched_path| touched_path.path == unmerged_path)
+                })
+                .collect_vec();
+            let (overridden, contested): (Vec<&TouchedPath>, Vec<&TouchedPath>) = template_conflicts
+                .into_iter()
+                .partition(|touched_path| overridable_remotes.contains(&touched_path.remote));
+            contested
+                .iter()
+                .for_each(|touched_path| eprintln!("[TEMPLATE CONFLICT] '{path}' has been modified by both '{other}' and '{remote}'", path = touched_path.path, other = touched_path.remote));
+            overridden
+                .iter()
+                .for_each(|touched_path| eprintln!("[OVERRIDDEN] '{path}' from '{remote}' overrides '{other}'", path = touched_path.path, other = touched_path.remote));
+            let overridden_paths = overridden
+                .iter()
+                .map(|touched_path| touched_path.path.as_str())
+                .collect_vec();
+            handle!(Self::resolve_conflicts_to_theirs(sh_dir, &overridden_paths), ResolveConflictsToTheirsFailed, remote, remote_branch_name);
+            overridden
+                .into_iter()
+                .chain(contested)
+                .map(|touched_path| touched_path.path.clone())
+                .collect_vec()
         } else {
-            vec!["--no-commit"]
+            Vec::new()
         };
 
-        handle!(cmd!(sh_dir, "git merge {remote}/{remote_branch_name} {flags...}").run_echo(), GitMergeFailed, remote, remote_branch_name);
+        if merge_result.is_err() && is_merging {
+            let picked_paths = handle!(Self::resolve_picked_conflicts(sh_dir, &remote, prefix.as_deref(), path.as_deref()), ResolvePickedConflictsFailed, remote, remote_branch_name);
+            picked_paths
+                .iter()
+                .for_each(|picked_path| eprintln!("[PICKED] '{picked_path}' conflicts with a commit picked from '{remote}' earlier, so the template version was kept"));
+        }
 
-        let merge_head_path = handle!(cmd!(sh_dir, "git rev-parse --path-format=absolute --git-path MERGE_HEAD").read(), GitMergeHeadPathFailed, remote, remote_branch_name);
-        if sh_dir.path_exists(merge_head_path) {
+        if let Err(source) = merge_result {
+            // The merge may fail only because of the conflicts that have been resolved above (in the filtered paths or in the paths overridden by a derived template)
+            let unmerged_paths = handle!(cmd!(sh_dir, "git diff --name-only --diff-filter=U").read(), GitUnmergedPathsReadFailed, remote, remote_branch_name);
+            if !is_merging || !unmerged_paths.is_empty() {
+                return Err(MergeTemplateFailed {
+                    source,
+                    remote,
+                    remote_branch_name,
+                });
+            }
+        }
+
+        // The changed paths are read before the lock is updated and before the hooks run, so that only the template changes are reported as overlaps
+        let changed_paths = handle!(cmd!(sh_dir, "git diff --cached --name-only {pre_merge_commit}").read(), GitChangedPathsReadFailed, remote);
+        let changed_paths = changed_paths.lines().map(ToString::to_string).collect_vec();
+        Self::report_overlaps(&remote, &changed_paths, touched_paths, &conflicted_paths);
+
+        // The updated template lock is committed together with the merge
+        // The picked commits come from the unsplit template history, so they are looked up in the remote branch instead of the merged (possibly split) ref
+        let remote_ref = format!("{remote}/{remote_branch_name}");
+        let is_lock_changed = handle!(Self::forget_merged_picks(sh_dir, &remote, &remote_ref), ForgetMergedPicksFailed, remote, remote_branch_name);
+
+        if is_merging {
+            handle!(provisioner.provision(sh_dir), ProvisionFailed, remote, remote_branch_name);
+            handle!(hook_runner.run(sh_dir, HookPhase::PreMergeCommit), RunPreMergeCommitHooksFailed, remote, remote_branch_name);
             handle!(cmd!(sh_dir, "git commit --no-edit").run_echo(), GitCommitFailed, remote, remote_branch_name);
+        } else if is_lock_changed {
+            let message = format!("Unlock the commits picked from {remote}");
+            handle!(cmd!(sh_dir, "git commit -m {message}").run_echo(), GitCommitFailed, remote, remote_branch_name);
+        }
+
+        Ok(changed_paths)
+    }
+
+    /// Reports the `changed_paths` that have been modified by another template earlier in the same run, except the `conflicted_paths` that have already been reported as conflicts between templates
+    fn report_overlaps(remote: &str, changed_paths: &[String], touched_paths: &[TouchedPath], conflicted_paths: &[String]) {
+        changed_paths
+            .iter()
+            .filter(|changed_path| !conflicted_paths.contains(changed_path))
+            .filter_map(|changed_path| {
+                touched_paths
+                    .iter()
+                    .find(|touched_path| touched_path.path == *changed_path && touched_path.remote != remote)
+            })
+            .for_each(|touched_path| {
+                eprintln!("[OVERLAP] '{path}' has been modified by both '{other}' and '{remote}'", path = touched_path.path, other = touched_path.remote);
+            });
+    }
+
+    /// Resolves the conflicts in the paths changed by the commits picked from `remote` (see [`TemplateLock`]) to the version of the merged template and returns the resolved paths
+    ///
+    /// The merged template contains the picked changes, so its version supersedes the version that has been picked earlier
+    fn resolve_picked_conflicts(sh_dir: &Shell, remote: &str, prefix: Option<&str>, path: Option<&str>) -> Result<Vec<String>, MergeCommandResolvePickedConflictsError> {
+        use MergeCommandResolvePickedConflictsError::*;
+        let picked_commits = handle!(TemplateLock::picked(sh_dir, remote), TemplateLockPickedFailed);
+        if picked_commits.is_empty() {
+            return Ok(Vec::new());
+        }
+        let template_paths = picked_commits
+            .iter()
+            .try_fold(Vec::<String>::new(), |mut template_paths, picked_commit| {
+                let changed_paths = handle!(cmd!(sh_dir, "git diff-tree --no-commit-id --name-only -r --root {picked_commit}").read(), GitDiffTreeFailed, picked_commit: picked_commit.as_str());
+                template_paths.extend(changed_paths.lines().map(ToOwned::to_owned));
+                Ok(template_paths)
+            })?;
+        // The picked commits come from the whole template repository, so their paths are mapped from the template `path` onto the `prefix`
+        let path_prefix = path.map(|path| format!("{path}/"));
+        let child_paths = template_paths
+            .iter()
+            .filter_map(|template_path| match &path_prefix {
+                Some(path_prefix) => template_path.strip_prefix(path_prefix.as_str()),
+                None => Some(template_path.as_str()),
+            })
+            .map(|template_path| match prefix {
+                Some(prefix) => format!("{prefix}/{template_path}"),
+                None => template_path.to_string(),
+            })
+            .collect_vec();
+        let unmerged_paths = handle!(cmd!(sh_dir, "git diff --name-only --diff-filter=U").read(), GitUnmergedPathsReadFailed);
+        let picked_paths = unmerged_paths
+            .lines()
+            .filter(|unmerged_path| {
+                child_paths
+                    .iter()
+                    .any(|child_path| child_path == unmerged_path)
+            })
+            .collect_vec();
+        handle!(Self::resolve_conflicts_to_theirs(sh_dir, &picked_paths), ResolveConflictsToTheirsFailed);
+        Ok(picked_paths.into_iter().map(ToOwned::to_owned).collect())
+    }
+
+    /// Resolves the conflicting `paths` to the version of the merged template ("theirs"); the paths that have been deleted in the merged template are removed
+    ///
+    /// PRUNING: Discards the local side of the conflicts in `paths`, because the merged template takes precedence over the template that has modified these paths earlier in the same run.
+    fn resolve_conflicts_to_theirs(sh_dir: &Shell, paths: &[&str]) -> Result<(), MergeCommandResolveConflictsToTheirsError> {
+        use MergeCommandResolveConflictsToTheirsError::*;
+        if paths.is_empty() {
+            return Ok(());
+        }
+        let records = handle!(cmd!(sh_dir, "git --literal-pathspecs ls-files --unmerged -z -- {paths...}").read(), GitLsFilesFailed);
+        let theirs_entries = records
+            .split('\0')
+            .filter_map(IndexEntry::parse)
+            .filter(|entry| entry.stage == "3")
+            .collect_vec();
+        let (theirs_paths, removed_paths): (Vec<&str>, Vec<&str>) = paths
+            .iter()
+            .copied()
+            .partition(|path| theirs_entries.iter().any(|entry| entry.path == *path));
+        if !theirs_paths.is_empty() {
+            let theirs_paths_slice = theirs_paths.as_slice();
+            handle!(cmd!(sh_dir, "git --literal-pathspecs checkout --theirs -- {theirs_paths_slice...}").run_echo(), GitCheckoutFailed);
+            handle!(cmd!(sh_dir, "git --literal-pathspecs add -- {theirs_paths...}").run_echo(), GitAddFailed);
+        }
+        if !removed_paths.is_empty() {
+            handle!(cmd!(sh_dir, "git --literal-pathspecs rm -q -f -- {removed_paths...}").run_echo(), GitRmFailed);
+        }
+        Ok(())
+    }
+
+    /// Returns the ref that should be merged from `remote`
+    ///
+    /// If the template lives in the `path` subdirectory of the template repository, splits the history of this subdirectory into a separate ref (like `git subtree split`), so that only this history is merged. The split is deterministic, so the split commits stay the same across merges.
+    pub fn resolve_merged_ref(sh_dir: &Shell, remote: &str, remote_branch_name: &str, path: Option<&str>) -> Result<String, MergeCommandResolveMergedRefError> {
+        use MergeCommandResolveMergedRefError::*;
+        let remote_ref = format!("{remote}/{remote_branch_name}");
+        match path {
+            None => Ok(remote_ref),
+            Some(path) => {
+                let split_commit = handle!(Self::split_commit(sh_dir, &remote_ref, path), SplitCommitFailed, remote_ref, path);
+                let split_ref = format!("{REPOCONF_SPLIT_REF_PREFIX}/{remote}/{remote_branch_name}");
+                handle!(cmd!(sh_dir, "git update-ref {split_ref} {split_commit}").run_echo(), GitUpdateRefFailed, split_ref);
+                Ok(split_ref)
+            }
+        }
+    }
+
+    /// Returns the commit that holds the history of the `path` subdirectory of `remote_ref` (like `git subtree split`) without writing any refs
+    pub fn split_commit(sh_dir: &Shell, remote_ref: &str, path: &str) -> Result<String, MergeCommandSplitCommitError> {
+        use MergeCommandSplitCommitError::*;
+        let split_commit = handle!(cmd!(sh_dir, "git subtree split --prefix={path} {remote_ref}").read(), GitSubtreeSplitFailed);
+        Ok(split_commit)
+    }
+
+    /// Copies the `managed` files from `merged_ref` into the current branch (or into the `prefix` subdirectory) and commits them as a normal commit with the synced template commit in the trailers (see [`Self::synced_commit`]), then returns the synced paths (before the hooks run)
+    ///
+    /// PRUNING: Removes the managed files that have been removed from the template since the previously synced commit, because the template owns these files.
+    fn sync_files(sh_dir: &Shell, context: MergeContext, remote: &str, merged_ref: &str, prefix: Option<&str>, managed: &[String]) -> Result<Vec<String>, MergeCommandSyncFilesError> {
+        use MergeCommandSyncFilesError::*;
+        let MergeContext {
+            hook_runner,
+            provisioner,
+            ..
+        } = context;
+        let template_commit_spec = format!("{merged_ref}^{{commit}}");
+        let template_commit = handle!(cmd!(sh_dir, "git rev-parse --verify {template_commit_spec}").read(), GitRevParseFailed, merged_ref);
+        let synced = handle!(Self::synced_commit(sh_dir, remote), SyncedCommitFailed);
+        if synced.as_deref() == Some(template_commit.as_str()) {
+            eprintln!("[INFO] The managed files of '{remote}' are already synced with {template_commit}");
+            return Ok(Vec::new());
+        }
+        if managed.is_empty() {
+            eprintln!("[WARN] Template '{remote}' doesn't declare any managed files; add them with `git config --add repoconf.{remote}.managed <glob>`");
+            return Ok(Vec::new());
+        }
+
+        let to_child_path = |path: &str| match prefix {
+            Some(prefix) => format!("{prefix}/{path}"),
+            None => path.to_string(),
+        };
+
+        let entries = handle!(Self::list_managed_files(sh_dir, &template_commit, managed), ListManagedFilesFailed, commit: template_commit.as_str());
+        entries.iter().try_for_each(|entry| {
+            let IndexEntry {
+                mode,
+                hash,
+                path,
+                ..
+            } = entry;
+            let cacheinfo = format!("{mode},{hash},{child_path}", child_path = to_child_path(path));
+            handle!(cmd!(sh_dir, "git update-index --add --cacheinfo {cacheinfo}").run_echo(), GitUpdateIndexFailed, path);
+            Ok(())
+        })?;
+        let child_paths = entries
+            .iter()
+            .map(|entry| to_child_path(&entry.path))
+            .collect::<Vec<_>>();
+        if !child_paths.is_empty() {
+            let child_paths_slice = child_paths.as_slice();
+            handle!(cmd!(sh_dir, "git checkout-index --force -- {child_paths_slice...}").run_echo(), GitCheckoutIndexFailed);
+        }
+
+        if let Some(synced) = synced {
+            let synced_entries = handle!(Self::list_managed_files(sh_dir, &synced, managed), ListManagedFilesFailed, commit: synced);
+            let removed_paths = synced_entries
+                .iter()
+                .map(|entry| to_child_path(&entry.path))
+                .filter(|child_path| !child_paths.contains(child_path))
+                .collect::<Vec<_>>();
+            if !removed_paths.is_empty() {
+                handle!(cmd!(sh_dir, "git --literal-pathspecs rm -q -f --ignore-unmatch -- {removed_paths...}").run_echo(), GitRmFailed);
+            }
         }
 
+        let synced_paths = handle!(cmd!(sh_dir, "git diff --cached --name-only HEAD").read(), GitSyncedPathsReadFailed);
+        if synced_paths.is_empty() {
+            eprintln!("[INFO] The managed files of '{remote}' are up to date");
+        } else {
+            let subject = format!("Sync managed files from {remote}");
+            let trailers = format!("Template-Remote: {remote}\nTemplate-Commit: {template_commit}");
+            handle!(provisioner.provision(sh_dir), ProvisionFailed);
+            handle!(hook_runner.run(sh_dir, HookPhase::PreMergeCommit), RunPreMergeCommitHooksFailed);
+            handle!(cmd!(sh_dir, "git commit -m {subject} -m {trailers}").run_echo(), GitCommitFailed);
+        }
+
+        Ok(synced_paths.lines().map(ToString::to_string).collect())
+    }
+
+    /// Returns the template commit that has been synced from `remote` last in the [`SyncMode::Files`] mode
+    ///
+    /// The commit is read from the `Template-Commit` trailer of the last sync commit of `remote` in the current branch, so it is available in every clone of the child repository
+    pub fn synced_commit(sh_dir: &Shell, remote: &str) -> Result<Option<String>, MergeCommandSyncedCommitError> {
+        use MergeCommandSyncedCommitError::*;
+        let remote_trailer_pattern = format!("^Template-Remote: {remote}$");
+        let synced = handle!(cmd!(sh_dir, "git log -1 --format=%(trailers:key=Template-Commit,valueonly) --grep={remote_trailer_pattern} HEAD").read(), GitLogFailed);
+        Ok(Some(synced).filter(|synced| !synced.is_empty()))
+    }
+
+    /// Lists the files of `commit` that match the `managed` globs
+    fn list_managed_files(sh_dir: &Shell, commit: &str, managed: &[String]) -> Result<Vec<IndexEntry>, MergeCommandListManagedFilesError> {
+        use MergeCommandListManagedFilesError::*;
+        // A temporary index allows to match the files of an arbitrary commit against pathspecs with glob magic
+        let index_path = handle!(cmd!(sh_dir, "git rev-parse --path-format=absolute --git-path repoconf/sync-index").read(), GitIndexPathFailed);
+        let pathspecs = managed
+            .iter()
+            .map(|glob| format!(":(top,glob){glob}"))
+            .collect::<Vec<_>>();
+        handle!(
+            cmd!(sh_dir, "git read-tree {commit}")
+                .env("GIT_INDEX_FILE", &index_path)
+                .run(),
+            GitReadTreeFailed
+        );
+        let records = handle!(
+            cmd!(sh_dir, "git ls-files --stage -z -- {pathspecs...}")
+                .env("GIT_INDEX_FILE", &index_path)
+                .read(),
+            GitLsFilesFailed
+        );
+        handle!(sh_dir.remove_path(&index_path), RemoveIndexFailed, index_path);
+        let entries = records
+            .split('\0')
+            .filter(|record| !record.is_empty())
+            .filter_map(IndexEntry::parse)
+            .collect();
+        Ok(entries)
+    }
+
+    /// Merges `merged_ref` into the current branch (or into the `prefix` subdirectory) without committing
+    ///
+    /// If `no_ff` is true, the merge always leaves a merge in progress, so that the merged paths can be filtered before committing
+    fn merge_template(sh_dir: &Shell, merged_ref: &str, prefix: Option<&str>, allow_unrelated_histories: bool, no_ff: bool) -> Result<(), MergeCommandMergeTemplateError> {
+        use MergeCommandMergeTemplateError::*;
+        match prefix {
+            None => {
+                let flags = [
+                    allow_unrelated_histories.then_some("--allow-unrelated-histories"),
+                    Some("--no-commit"),
+                    no_ff.then_some("--no-ff"),
+                ]
+                .into_iter()
+                .flatten()
+                .collect::<Vec<_>>();
+                handle!(cmd!(sh_dir, "git merge {merged_ref} {flags...}").run_echo(), GitMergeFailed, merged_ref);
+            }
+            Some(prefix) => handle!(Self::merge_subtree(sh_dir, merged_ref, prefix, allow_unrelated_histories), MergeSubtreeFailed, merged_ref, prefix),
+        }
         Ok(())
     }
 
-    fn run_post_merge(sh_dir: &Shell, path: PathBuf) -> Result<(), MergeCommandRunPostMergeError> {
-        use MergeCommandRunPostMergeError::*;
-        if sh_dir.path_exists(&path) {
-            handle!(cmd!(sh_dir, "bash {path}").run_interactive(), RunInteractiveFailed, path);
+    /// Reverts the merged paths that don't match the `include` globs or match the `exclude` globs to their state before the merge ("ours")
+    ///
+    /// Returns the reverted paths
+    ///
+    /// PRUNING: Discards the template version of the filtered paths (and deletes the filtered paths that are new in the template), because the child repository has opted out of receiving them.
+    fn filter_paths(sh_dir: &Shell, prefix: Option<&str>, include: &[String], exclude: &[String]) -> Result<Vec<String>, MergeCommandFilterPathsError> {
+        use MergeCommandFilterPathsError::*;
+        let root = prefix
+            .map(|prefix| format!("{prefix}/"))
+            .unwrap_or_default();
+        let include_pathspecs = if include.is_empty() {
+            vec![format!(":(top,glob){root}**")]
+        } else {
+            include
+                .iter()
+                .map(|glob| format!(":(top,glob){root}{glob}"))
+                .collect()
+        };
+        let exclude_pathspecs = exclude
+            .iter()
+            .map(|glob| format!(":(top,exclude,glob){root}{glob}"));
+        let pathspecs = include_pathspecs
+            .into_iter()
+            .chain(exclude_pathspecs)
+            .collect::<Vec<_>>();
+
+        let merged_paths = handle!(cmd!(sh_dir, "git diff --cached --name-only HEAD").read(), GitDiffMergedPathsFailed);
+        let kept_paths = handle!(cmd!(sh_dir, "git diff --cached --name-only HEAD -- {pathspecs...}").read(), GitDiffKeptPathsFailed);
+        let filtered_paths = merged_paths
+            .lines()
+            .filter(|merged_path| {
+                !kept_paths
+                    .lines()
+                    .any(|kept_path| kept_path == *merged_path)
+            })
+            .map(ToString::to_string)
+            .collect::<Vec<_>>();
+        if filtered_paths.is_empty() {
+            return Ok(filtered_paths);
+        }
+
+        let filtered_paths_slice = filtered_paths.as_slice();
+        let existing_paths = handle!(cmd!(sh_dir, "git --literal-pathspecs ls-tree -r --name-only HEAD -- {filtered_paths_slice...}").read(), GitLsTreeFailed);
+        let existing_paths = existing_paths.lines().collect::<Vec<_>>();
+        let (restored_paths, removed_paths): (Vec<&str>, Vec<&str>) = filtered_paths
+            .iter()
+            .map(String::as_str)
+            .partition(|filtered_path| existing_paths.contains(filtered_path));
+        if !restored_paths.is_empty() {
+            handle!(cmd!(sh_dir, "git --literal-pathspecs checkout HEAD -- {restored_paths...}").run_echo(), GitCheckoutFailed);
+        }
+        if !removed_paths.is_empty() {
+            handle!(cmd!(sh_dir, "git --literal-pathspecs rm -f -q -- {removed_paths...}").run_echo(), GitRmFailed);
+        }
+        Ok(filtered_paths)
+    }
+
+    /// Merges `merged_ref` into the `prefix` subdirectory without committing
+    ///
+    /// The first merge reads the template tree into `prefix` (like `git subtree add`), the subsequent merges use the `subtree` option of the merge strategy to map the template tree onto `prefix`
+    fn merge_subtree(sh_dir: &Shell, merged_ref: &str, prefix: &str, allow_unrelated_histories: bool) -> Result<(), MergeCommandMergeSubtreeError> {
+        use MergeCommandMergeSubtreeError::*;
+        let prefix_tree = format!("HEAD:{prefix}");
+        let prefix_tree_hash = handle!(
+            cmd!(sh_dir, "git rev-parse --verify --quiet {prefix_tree}")
+                .ignore_status()
+                .read(),
+            GitPrefixTreeReadFailed
+        );
+        if prefix_tree_hash.is_empty() {
+            // The template history is unrelated to the child history until the first subtree merge, so `--allow-unrelated-histories` is always required here
+            handle!(cmd!(sh_dir, "git merge --strategy ours --allow-unrelated-histories --no-commit {merged_ref}").run_echo(), GitMergeOursFailed);
+            let prefix_dir = format!("{prefix}/");
+            handle!(cmd!(sh_dir, "git read-tree --prefix={prefix_dir} -u {merged_ref}").run_echo(), GitReadTreeFailed);
+        } else {
+            let subtree_flag = format!("-Xsubtree={prefix}");
+            let flags = if allow_unrelated_histories {
+                vec![
+                    "--allow-unrelated-histories",
+                    "--no-commit",
+                    subtree_flag.as_str(),
+                ]
+            } else {
+                vec!["--no-commit", subtree_flag.as_str()]
+            };
+            handle!(cmd!(sh_dir, "git merge {merged_ref} {flags...}").run_echo(), GitMergeFailed);
         }
         Ok(())
     }
+
+    /// Removes the commits that are contained in `remote_ref` from the commits picked from `remote` by [`PickCommand`](crate::PickCommand) (see [`TemplateLock`]), stages the updated lock and returns true if the lock has changed
+    ///
+    /// The `remote_ref` must be the unsplit remote branch (not the split ref of a template subdirectory), because the picked commits come from the unsplit history.
+    ///
+    /// PRUNING: Removes the template lock entries of the picked commits that have been merged, because the full merge supersedes them.
+    pub fn forget_merged_picks(sh_dir: &Shell, remote: &str, remote_ref: &str) -> Result<bool, MergeCommandForgetMergedPicksError> {
+        use MergeCommandForgetMergedPicksError::*;
+        let picked_commits = handle!(TemplateLock::picked(sh_dir, remote), TemplateLockPickedFailed);
+        let merged_commits = picked_commits
+            .into_iter()
+            .try_fold(Vec::<String>::new(), |mut merged_commits, picked_commit| {
+                let is_merged = handle!(sh_dir.git_is_ancestor(&picked_commit, remote_ref), GitIsAncestorFailed);
+                if is_merged {
+                    merged_commits.push(picked_commit);
+                }
+                Ok(merged_commits)
+            })?;
+        merged_commits.iter().try_for_each(|merged_commit| {
+            handle!(TemplateLock::remove_picked(sh_dir, remote, merged_commit), TemplateLockRemovePickedFailed, merged_commit: merged_commit.as_str());
+            Ok(())
+        })?;
+        Ok(!merged_commits.is_empty())
+    }
 }
 
 #[derive(Error, Debug)]
@@ -232,14 +1390,32 @@ pub enum MergeCommandRunError {
     UnwrapOrCurrentDirFailed { source: UnwrapOrCurrentDirError },
     #[error("failed to create a shell instance")]
     ShellNewFailed { source: xshell::Error },
+    #[error("failed to resolve the worktree directory")]
+    GitWorktreeDirReadFailed { source: xshell::Error },
+    #[error("failed to load the provisioner config")]
+    ProvisionerLoadFailed { source: ProvisionerLoadError },
+    #[error("failed to abort the merge")]
+    AbortMergeFailed { source: MergeCommandAbortMergeError },
+    #[error("failed to read the branch of the merge in progress")]
+    GitMergeBranchReadFailed { source: xshell::Error },
+    #[error("failed to read the URLs of the templates")]
+    WithTemplateUrlsFailed { source: HookRunnerWithTemplateUrlsError },
+    #[error("failed to extract the template-owned hooks")]
+    WithTemplateHooksFailed { source: MergeCommandWithTemplateHooksError },
     #[error("failed to continue the merge")]
     ContinueMergeFailed { source: MergeCommandContinueMergeError },
     #[error("failed to read git remote names")]
     GitRemoteNamesFailed { source: GitRemoteNamesError },
     #[error("failed to check repository status")]
     IsCleanRepoFailed { source: IsCleanRepoError },
+    #[error("failed to read the stash ref")]
+    GitStashRefReadFailed { source: xshell::Error },
+    #[error("the changes stashed by an earlier run ({stash_commit}) haven't been restored yet; finish that merge with `--continue --stash` or `--abort --stash` first")]
+    StashAlreadyExists { stash_commit: String },
     #[error("repository '{dir}' has uncommitted changes")]
     RepositoryNotClean { dir: PathBuf },
+    #[error("failed to stash uncommitted changes")]
+    StashPushFailed { source: MergeCommandStashPushError },
     #[error("failed to read git refs")]
     GitRefsFailed { source: GitRefsError },
     #[error("failed to resolve local branch name for prefix '{prefix}'")]
@@ -248,16 +1424,210 @@ pub enum MergeCommandRunError {
     GitLocalBranchExistsFailed { source: GitLocalBranchExistsError, branch_name: String },
     #[error("local branch '{branch_name}' does not exist")]
     LocalBranchDoesNotExist { branch_name: String },
+    #[error("failed to compute the backup timestamp")]
+    UnixTimestampFailed { source: UnixTimestampError },
+    #[error("failed to record the merge state")]
+    RecordMergeStateFailed { source: MergeCommandRecordMergeStateError },
     #[error("failed to check out local branch '{branch_name}'")]
     GitCheckoutFailed { source: xshell::Error, branch_name: String },
+    #[error("failed to add a worktree for the merge")]
+    AddWorktreeFailed { source: MergeCommandAddWorktreeError },
     #[error("failed to update repoconf remotes")]
     GitRemoteUpdateFailed { source: xshell::Error, remotes: Vec<String> },
     #[error("failed to merge remotes")]
     MergeRemotesFailed { source: MergeCommandMergeRemotesError },
-    #[error("failed to run the post-merge hook")]
-    RunPostMergeFailed { source: MergeCommandRunPostMergeError },
+    #[error("failed to read the commit before the merge")]
+    GitPreMergeCommitReadFailed { source: xshell::Error },
+    #[error("failed to read the merged commit")]
+    GitMergedCommitReadFailed { source: xshell::Error },
+    #[error("failed to run the {phase} hooks")]
+    RunHooksFailed { source: HookRunnerRunError, phase: HookPhase },
+    #[error("failed to read the verification command from git config")]
+    GitConfigGetAllFailed { source: GitConfigGetAllError },
+    #[error("failed to verify the merge with '{verify}'")]
+    VerifyFailed { source: MergeCommandVerifyError, verify: String },
+    #[error("failed to discard the merge that failed verification")]
+    DiscardUnverifiedMergeFailed { source: MergeCommandDiscardUnverifiedMergeError },
+    #[error("failed to record the merge result")]
+    RecordMergeResultFailed { source: MergeCommandRecordMergeResultError },
+    #[error("failed to finish the merge in the worktree")]
+    FinishWorktreeFailed { source: MergeCommandFinishWorktreeError },
     #[error("failed to push merged changes")]
     GitPushFailed { source: xshell::Error },
+    #[error("failed to update branches matching {rebase_branches:?}")]
+    RebaseBranchesFailed { source: MergeCommandRebaseBranchesError, rebase_branches: Vec<String> },
+    #[error("failed to switch back to the previously checked out branch")]
+    RestorePreviousHeadFailed { source: MergeCommandRestorePreviousHeadError },
+    #[error("failed to forget the state of the pushed merge")]
+    ForgetMergeStateFailed { source: MergeCommandForgetMergeStateError },
+    #[error("failed to restore stashed changes")]
+    StashPopFailed { source: MergeCommandStashPopError },
+}
+
+#[derive(Error, Debug)]
+pub enum MergeCommandRebaseBranchesError {
+    #[error("failed to read the merge branch")]
+    GitMergeBranchReadFailed { source: xshell::Error },
+    #[error("failed to list branches matching {branch_patterns:?}")]
+    GitForEachRefFailed { source: xshell::Error, branch_patterns: Vec<String> },
+    #[error("failed to update branch '{branch_name}' (updated branches: [{updated}])", updated = updated_branch_names.join(", "))]
+    RebaseBranchFailed { source: MergeCommandRebaseBranchError, branch_name: String, updated_branch_names: Vec<String> },
+    #[error("failed to switch back to branch '{local_branch_name}'")]
+    GitCheckoutFailed { source: xshell::Error, local_branch_name: String },
+}
+
+#[derive(Error, Debug)]
+pub enum MergeCommandRebaseBranchError {
+    #[error("failed to rebase the branch (the rebase has been aborted)")]
+    GitRebaseFailed { source: xshell::Error },
+    #[error("failed to resolve the rebase state path")]
+    GitRebaseMergePathFailed { source: xshell::Error },
+    #[error("failed to abort the rebase")]
+    GitRebaseAbortFailed { source: xshell::Error },
+    #[error("failed to check out the branch")]
+    GitCheckoutFailed { source: xshell::Error },
+    #[error("failed to merge into the branch (the merge has been aborted)")]
+    GitMergeFailed { source: xshell::Error },
+    #[error("failed to resolve the merge state path")]
+    GitMergeHeadPathFailed { source: xshell::Error },
+    #[error("failed to abort the merge")]
+    GitMergeAbortFailed { source: xshell::Error },
+}
+
+#[derive(Error, Debug)]
+pub enum MergeCommandStashPushError {
+    #[error("failed to stash uncommitted changes")]
+    GitStashPushFailed { source: xshell::Error },
+    #[error("failed to read the stash commit")]
+    GitStashRevParseFailed { source: xshell::Error },
+    #[error("failed to remember the stash commit '{stash_commit}'")]
+    GitUpdateRefFailed { source: xshell::Error, stash_commit: String },
+}
+
+#[derive(Error, Debug)]
+pub enum MergeCommandRestoreStashError {
+    #[error("failed to read the stash ref")]
+    GitStashRefReadFailed { source: xshell::Error },
+    #[error("failed to resolve the merge state path")]
+    GitMergeHeadPathFailed { source: xshell::Error },
+    #[error("failed to re-apply the stashed changes")]
+    StashPopFailed { source: MergeCommandStashPopError },
+}
+
+#[derive(Error, Debug)]
+pub enum MergeCommandStashPopError {
+    #[error("failed to read the stash ref")]
+    GitStashRefReadFailed { source: xshell::Error },
+    #[error("failed to re-apply stash '{stash_commit}' cleanly; the stash is kept: resolve the conflicts, then run `git stash drop` and `git update-ref -d refs/repoconf/stash`")]
+    GitStashApplyFailed { source: xshell::Error, stash_commit: String },
+    #[error("failed to read the top of the stash list")]
+    GitStashTopReadFailed { source: xshell::Error },
+    #[error("failed to drop re-applied stash '{stash_commit}'")]
+    GitStashDropFailed { source: xshell::Error, stash_commit: String },
+    #[error("failed to forget the stash ref")]
+    GitUpdateRefDeleteFailed { source: xshell::Error },
+}
+
+#[derive(Error, Debug)]
+pub enum MergeCommandAddWorktreeError {
+    #[error("failed to add a worktree at '{worktree_dir}' for branch '{local_branch_name}'")]
+    GitWorktreeAddFailed { source: xshell::Error, worktree_dir: PathBuf, local_branch_name: String },
+}
+
+#[derive(Error, Debug)]
+pub enum MergeCommandRecordMergeStateError {
+    #[error("failed to read the current branch")]
+    GitCurrentRefReadFailed { source: xshell::Error },
+    #[error("failed to read the current commit")]
+    GitCurrentCommitReadFailed { source: xshell::Error },
+    #[error("failed to remember the current commit '{current_commit}'")]
+    GitUpdatePreviousHeadRefFailed { source: xshell::Error, current_commit: String },
+    #[error("failed to remember the current branch '{current_ref}'")]
+    GitSymbolicPreviousHeadRefFailed { source: xshell::Error, current_ref: String },
+    #[error("failed to forget the post-merge commit of the previous merge")]
+    GitUpdatePostMergeRefDeleteFailed { source: xshell::Error },
+    #[error("failed to remember the merge branch '{local_branch_ref}'")]
+    GitSymbolicMergeBranchRefFailed { source: xshell::Error, local_branch_ref: String },
+    #[error("failed to remember the pre-merge commit of '{local_branch_ref}'")]
+    GitUpdatePreMergeRefFailed { source: xshell::Error, local_branch_ref: String },
+    #[error("failed to back up '{local_branch_ref}' to '{backup_ref}'")]
+    GitUpdateBackupRefFailed { source: xshell::Error, backup_ref: String, local_branch_ref: String },
+}
+
+#[derive(Error, Debug)]
+pub enum MergeCommandRecordMergeResultError {
+    #[error("failed to read the merged commit")]
+    GitRevParseHeadFailed { source: xshell::Error },
+    #[error("failed to remember the merged commit '{merged_commit}'")]
+    GitUpdateRefFailed { source: xshell::Error, merged_commit: String },
+}
+
+#[derive(Error, Debug)]
+pub enum MergeCommandForgetMergeStateError {
+    #[error("failed to delete '{git_ref}'")]
+    GitUpdateRefDeleteFailed { source: xshell::Error, git_ref: String },
+}
+
+#[derive(Error, Debug)]
+pub enum MergeCommandAbortMergeError {
+    #[error("failed to remove the worktree at '{worktree_dir}'")]
+    GitWorktreeRemoveFailed { source: xshell::Error, worktree_dir: PathBuf },
+    #[error("failed to read the merge branch (is a merge in progress?)")]
+    GitMergeBranchReadFailed { source: xshell::Error },
+    #[error("failed to read the current branch")]
+    GitCurrentRefReadFailed { source: xshell::Error },
+    #[error("the merge was performed on '{local_branch_ref}', but the current branch is '{current_ref}'")]
+    NotOnMergeBranch { local_branch_ref: String, current_ref: String },
+    #[error("failed to resolve the merge state path")]
+    GitMergeHeadPathFailed { source: xshell::Error },
+    #[error("failed to read the post-merge commit")]
+    GitPostMergeCommitReadFailed { source: xshell::Error },
+    #[error("failed to read the current commit")]
+    GitCurrentCommitReadFailed { source: xshell::Error },
+    #[error("no template merge is in progress on '{local_branch_ref}' and it doesn't point to the last merge result; aborting would discard the commits made since the merge")]
+    MergeNotInProgress { local_branch_ref: String },
+    #[error("failed to abort the in-progress merge")]
+    GitMergeAbortFailed { source: xshell::Error },
+    #[error("failed to reset '{local_branch_ref}' to its pre-merge commit")]
+    GitResetFailed { source: xshell::Error, local_branch_ref: String },
+    #[error("failed to switch back to the previously checked out branch")]
+    RestorePreviousHeadFailed { source: MergeCommandRestorePreviousHeadError },
+    #[error("failed to forget the merge state")]
+    ForgetMergeStateFailed { source: MergeCommandForgetMergeStateError },
+}
+
+#[derive(Error, Debug)]
+pub enum MergeCommandRestorePreviousHeadError {
+    #[error("failed to read the previously checked out branch")]
+    GitPreviousBranchReadFailed { source: xshell::Error },
+    #[error("failed to read the previously checked out commit")]
+    GitPreviousCommitReadFailed { source: xshell::Error },
+    #[error("failed to check out commit '{previous_commit}'")]
+    GitCheckoutCommitFailed { source: xshell::Error, previous_commit: String },
+    #[error("failed to check out branch '{previous_branch_name}'")]
+    GitCheckoutBranchFailed { source: xshell::Error, previous_branch_name: String },
+}
+
+#[derive(Error, Debug)]
+pub enum MergeCommandFinishWorktreeError {
+    #[error("failed to read the merge branch (is a merge in progress?)")]
+    GitSymbolicRefReadFailed { source: xshell::Error },
+    #[error("failed to read the merge result in worktree '{worktree_dir}'")]
+    GitRevParseHeadFailed { source: xshell::Error, worktree_dir: PathBuf },
+    #[error("failed to read the current branch")]
+    GitCurrentRefReadFailed { source: xshell::Error },
+    #[error("failed to fast-forward the checked out branch '{local_branch_name}' to '{merged_commit}' (commit or stash the conflicting changes and run `git merge --ff-only {merged_commit}`, then push)")]
+    GitMergeFfOnlyFailed { source: xshell::Error, local_branch_name: String, merged_commit: String },
+    #[error("failed to move '{local_branch_ref}' to '{merged_commit}'")]
+    GitUpdateRefFailed { source: xshell::Error, local_branch_ref: String, merged_commit: String },
+    #[error("failed to read the upstream remote of branch '{local_branch_name}'")]
+    GitUpstreamRemoteReadFailed { source: xshell::Error, local_branch_name: String },
+    #[error("failed to read the upstream branch of branch '{local_branch_name}'")]
+    GitUpstreamRefReadFailed { source: xshell::Error, local_branch_name: String },
+    #[error("failed to push the merge result to '{upstream_ref}' on remote '{upstream_remote}'")]
+    GitPushFailed { source: xshell::Error, upstream_remote: String, upstream_ref: String },
+    #[error("failed to remove the worktree at '{worktree_dir}'")]
+    GitWorktreeRemoveFailed { source: xshell::Error, worktree_dir: PathBuf },
 }
 
 #[derive(Error, Debug)]
@@ -270,46 +1640,294 @@ pub enum MergeCommandContinueMergeError {
     UnmergedPathsReadFailed { source: xshell::Error },
     #[error("merge conflicts remain:\n{paths}")]
     UnresolvedConflicts { paths: String },
-    #[error("failed to install mise if the repository is configured")]
-    InstallMiseIfRepositoryConfiguredFailed { source: MergeCommandInstallMiseIfRepositoryConfiguredError },
+    #[error("failed to provision the toolchain")]
+    ProvisionFailed { source: ProvisionerProvisionError },
+    #[error("failed to run the pre-merge-commit hooks")]
+    RunPreMergeCommitHooksFailed { source: HookRunnerRunError },
     #[error("failed to commit the resolved merge")]
     GitCommitFailed { source: xshell::Error },
 }
 
 #[derive(Error, Debug)]
-pub enum MergeCommandInstallMiseIfRepositoryConfiguredError {
-    #[error("failed to resolve the repository root")]
-    GitRepositoryRootReadFailed { source: xshell::Error },
-    #[error("failed to list mise config files")]
-    MiseConfigListFailed { source: xshell::Error },
-    #[error("failed to deserialize the mise config file list")]
-    FromStrFailed { source: serde_json::Error, json: String },
-    #[error("mise returned a config file entry without a string path")]
-    MiseConfigListInvalid { configs: Vec<Value> },
-    #[error("failed to install mise tools and hooks before committing the merge")]
-    MiseInstallFailed { source: xshell::Error },
+pub enum MergeCommandVerifyError {
+    #[error("failed to run the verification command")]
+    RunCommandFailed { source: HookRunnerRunCommandError },
+}
+
+#[derive(Error, Debug)]
+pub enum MergeCommandDiscardUnverifiedMergeError {
+    #[error("failed to read the branch of the merge in progress")]
+    GitMergeBranchReadFailed { source: xshell::Error },
+    #[error("failed to read the merged commit")]
+    GitRevParseHeadFailed { source: xshell::Error },
+    #[error("failed to keep the merge on branch '{unverified_branch}'")]
+    GitBranchFailed { source: xshell::Error, unverified_branch: String },
+    #[error("failed to remove the worktree at '{worktree_dir}'")]
+    GitWorktreeRemoveFailed { source: xshell::Error, worktree_dir: PathBuf },
+    #[error("failed to reset branch '{local_branch_name}' to the pre-merge commit")]
+    GitResetFailed { source: xshell::Error, local_branch_name: String },
+}
+
+#[derive(Error, Debug)]
+pub enum MergeCommandWithTemplateHooksError {
+    #[error("failed to order the template remotes")]
+    OrderRemotesFailed { source: MergeCommandOrderRemotesError },
+    #[error("failed to load the config of template '{remote}'")]
+    TemplateConfigLoadFailed { source: TemplateConfigLoadError, remote: String },
+    #[error("failed to resolve remote branch name for '{remote}' with prefix '{prefix}'")]
+    RemoteBranchNameResolveFailed { source: BranchNameStrategyToBranchNameError, remote: String, prefix: String },
+    #[error("failed to resolve the merged ref of template '{remote}'")]
+    ResolveMergedRefFailed { source: MergeCommandResolveMergedRefError, remote: String },
+    #[error("failed to extract the hooks of template '{remote}' at '{merged_ref}'")]
+    WithTemplateHooksFailed { source: HookRunnerWithTemplateHooksError, remote: String, merged_ref: String },
 }
 
 #[derive(Error, Debug)]
 pub enum MergeCommandMergeRemotesError {
+    #[error("failed to order the template remotes")]
+    OrderRemotesFailed { source: MergeCommandOrderRemotesError },
     #[error("failed to merge from remote '{remote}'")]
     MergeRemoteFailed { source: MergeCommandMergeRemoteError, remote: String },
 }
 
 #[derive(Error, Debug)]
 pub enum MergeCommandMergeRemoteError {
+    #[error("failed to read the commit before merging from '{remote}'")]
+    GitPreMergeCommitReadFailed { source: xshell::Error, remote: String },
     #[error("failed to resolve remote branch name for '{remote}' with prefix '{prefix}'")]
     RemoteBranchNameResolveFailed { source: BranchNameStrategyToBranchNameError, prefix: String, remote: String },
-    #[error("failed to merge from '{remote}/{remote_branch_name}'")]
-    GitMergeFailed { source: xshell::Error, remote: String, remote_branch_name: String },
+    #[error("failed to load the config of template '{remote}'")]
+    TemplateConfigLoadFailed { source: TemplateConfigLoadError, remote: String },
+    #[error("failed to resolve the ref to merge from '{remote}/{remote_branch_name}'")]
+    ResolveMergedRefFailed { source: MergeCommandResolveMergedRefError, remote: String, remote_branch_name: String },
+    #[error("failed to prepare the hook environment for '{remote}/{remote_branch_name}'")]
+    HookRunnerWithTemplateFailed { source: HookRunnerWithTemplateError, remote: String, remote_branch_name: String },
+    #[error("failed to sync the managed files from '{remote}/{remote_branch_name}'")]
+    SyncFilesFailed { source: MergeCommandSyncFilesError, remote: String, remote_branch_name: String },
     #[error("failed to resolve the merge state path after merging from '{remote}/{remote_branch_name}'")]
     GitMergeHeadPathFailed { source: xshell::Error, remote: String, remote_branch_name: String },
+    #[error("failed to filter the paths merged from '{remote}/{remote_branch_name}'")]
+    FilterPathsFailed { source: MergeCommandFilterPathsError, remote: String, remote_branch_name: String },
+    #[error("failed to resolve the conflicts overridden by '{remote}/{remote_branch_name}'")]
+    ResolveConflictsToTheirsFailed { source: MergeCommandResolveConflictsToTheirsError, remote: String, remote_branch_name: String },
+    #[error("failed to resolve the conflicts with the commits picked from '{remote}/{remote_branch_name}'")]
+    ResolvePickedConflictsFailed { source: MergeCommandResolvePickedConflictsError, remote: String, remote_branch_name: String },
+    #[error("failed to read the unmerged paths after merging from '{remote}/{remote_branch_name}'")]
+    GitUnmergedPathsReadFailed { source: xshell::Error, remote: String, remote_branch_name: String },
+    #[error("failed to merge from '{remote}/{remote_branch_name}'")]
+    MergeTemplateFailed { source: MergeCommandMergeTemplateError, remote: String, remote_branch_name: String },
+    #[error("failed to provision the toolchain before committing the merge from '{remote}/{remote_branch_name}'")]
+    ProvisionFailed { source: ProvisionerProvisionError, remote: String, remote_branch_name: String },
+    #[error("failed to run the pre-merge-commit hooks for the merge from '{remote}/{remote_branch_name}'")]
+    RunPreMergeCommitHooksFailed { source: HookRunnerRunError, remote: String, remote_branch_name: String },
     #[error("failed to commit the merge from '{remote}/{remote_branch_name}'")]
     GitCommitFailed { source: xshell::Error, remote: String, remote_branch_name: String },
+    #[error("failed to update the picked commits after merging from '{remote}/{remote_branch_name}'")]
+    ForgetMergedPicksFailed { source: MergeCommandForgetMergedPicksError, remote: String, remote_branch_name: String },
+    #[error("failed to read the paths changed by merging from '{remote}'")]
+    GitChangedPathsReadFailed { source: xshell::Error, remote: String },
+}
+
+#[derive(Error, Debug)]
+pub enum MergeCommandResolveConflictsToTheirsError {
+    #[error("failed to list the unmerged entries")]
+    GitLsFilesFailed { source: xshell::Error },
+    #[error("failed to check out the template version of the conflicting paths")]
+    GitCheckoutFailed { source: xshell::Error },
+    #[error("failed to stage the template version of the conflicting paths")]
+    GitAddFailed { source: xshell::Error },
+    #[error("failed to remove the conflicting paths that have been deleted in the template")]
+    GitRmFailed { source: xshell::Error },
+}
+
+#[derive(Error, Debug)]
+pub enum MergeCommandOrderRemotesError {
+    #[error("failed to load the configs of {len} templates", len = source.len())]
+    TemplateConfigLoadFailed { source: ErrVec<TemplateConfigLoadError> },
+    #[error("failed to resolve the layers of {len} templates", len = source.len())]
+    TemplateLayerFailed { source: ErrVec<MergeCommandTemplateLayerError> },
 }
 
 #[derive(Error, Debug)]
-pub enum MergeCommandRunPostMergeError {
-    #[error("failed to run the post-merge hook '{path}'")]
-    RunInteractiveFailed { source: xshell::Error, path: PathBuf },
+pub enum MergeCommandTemplateLayerError {
+    #[error("template '{remote}' extends itself via {ancestors:?}")]
+    CycleDetected { remote: String, ancestors: Vec<String> },
+}
+
+#[derive(Error, Debug)]
+pub enum MergeCommandResolveMergedRefError {
+    #[error("failed to split the history of '{path}' from '{remote_ref}'")]
+    SplitCommitFailed { source: MergeCommandSplitCommitError, remote_ref: String, path: String },
+    #[error("failed to update '{split_ref}'")]
+    GitUpdateRefFailed { source: xshell::Error, split_ref: String },
+}
+
+#[derive(Error, Debug)]
+pub enum MergeCommandSplitCommitError {
+    #[error("failed to run 'git subtree split'")]
+    GitSubtreeSplitFailed { source: xshell::Error },
+}
+
+#[derive(Error, Debug)]
+pub enum MergeCommandSyncFilesError {
+    #[error("failed to resolve the commit of '{merged_ref}'")]
+    GitRevParseFailed { source: xshell::Error, merged_ref: String },
+    #[error("failed to read the previously synced template commit")]
+    SyncedCommitFailed { source: MergeCommandSyncedCommitError },
+    #[error("failed to list the managed files of commit '{commit}'")]
+    ListManagedFilesFailed { source: MergeCommandListManagedFilesError, commit: String },
+    #[error("failed to stage the managed file '{path}'")]
+    GitUpdateIndexFailed { source: xshell::Error, path: String },
+    #[error("failed to write the managed files to the working tree")]
+    GitCheckoutIndexFailed { source: xshell::Error },
+    #[error("failed to remove the managed files that have been removed from the template")]
+    GitRmFailed { source: xshell::Error },
+    #[error("failed to read the synced paths")]
+    GitSyncedPathsReadFailed { source: xshell::Error },
+    #[error("failed to provision the toolchain before committing the managed files")]
+    ProvisionFailed { source: ProvisionerProvisionError },
+    #[error("failed to run the pre-merge-commit hooks")]
+    RunPreMergeCommitHooksFailed { source: HookRunnerRunError },
+    #[error("failed to commit the managed files")]
+    GitCommitFailed { source: xshell::Error },
+}
+
+#[derive(Error, Debug)]
+pub enum MergeCommandSyncedCommitError {
+    #[error("failed to find the last sync commit")]
+    GitLogFailed { source: xshell::Error },
+}
+
+#[derive(Error, Debug)]
+pub enum MergeCommandListManagedFilesError {
+    #[error("failed to resolve the path of the temporary index")]
+    GitIndexPathFailed { source: xshell::Error },
+    #[error("failed to read the commit into the temporary index")]
+    GitReadTreeFailed { source: xshell::Error },
+    #[error("failed to list the managed files in the temporary index")]
+    GitLsFilesFailed { source: xshell::Error },
+    #[error("failed to remove the temporary index '{index_path}'")]
+    RemoveIndexFailed { source: xshell::Error, index_path: String },
+}
+
+#[derive(Error, Debug)]
+pub enum MergeCommandMergeTemplateError {
+    #[error("failed to merge '{merged_ref}'")]
+    GitMergeFailed { source: xshell::Error, merged_ref: String },
+    #[error("failed to merge '{merged_ref}' into subdirectory '{prefix}'")]
+    MergeSubtreeFailed { source: MergeCommandMergeSubtreeError, merged_ref: String, prefix: String },
+}
+
+#[derive(Error, Debug)]
+pub enum MergeCommandFilterPathsError {
+    #[error("failed to list the merged paths")]
+    GitDiffMergedPathsFailed { source: xshell::Error },
+    #[error("failed to list the merged paths that match the include/exclude config")]
+    GitDiffKeptPathsFailed { source: xshell::Error },
+    #[error("failed to list the filtered paths that exist before the merge")]
+    GitLsTreeFailed { source: xshell::Error },
+    #[error("failed to restore the filtered paths")]
+    GitCheckoutFailed { source: xshell::Error },
+    #[error("failed to remove the filtered paths")]
+    GitRmFailed { source: xshell::Error },
+}
+
+#[derive(Error, Debug)]
+pub enum MergeCommandMergeSubtreeError {
+    #[error("failed to check whether the subdirectory exists")]
+    GitPrefixTreeReadFailed { source: xshell::Error },
+    #[error("failed to start the initial subtree merge")]
+    GitMergeOursFailed { source: xshell::Error },
+    #[error("failed to read the template tree into the subdirectory")]
+    GitReadTreeFailed { source: xshell::Error },
+    #[error("failed to merge into the subdirectory")]
+    GitMergeFailed { source: xshell::Error },
+}
+
+#[derive(Error, Debug)]
+pub enum MergeCommandForgetMergedPicksError {
+    #[error("failed to read the picked commits")]
+    TemplateLockPickedFailed { source: TemplateLockPickedError },
+    #[error("failed to check whether a picked commit has been merged")]
+    GitIsAncestorFailed { source: GitIsAncestorError },
+    #[error("failed to remove '{merged_commit}' from the template lock")]
+    TemplateLockRemovePickedFailed { source: TemplateLockRemovePickedError, merged_commit: String },
+}
+
+#[derive(Error, Debug)]
+pub enum MergeCommandResolvePickedConflictsError {
+    #[error("failed to read the picked commits")]
+    TemplateLockPickedFailed { source: TemplateLockPickedError },
+    #[error("failed to list the paths changed by the picked commit '{picked_commit}'")]
+    GitDiffTreeFailed { source: xshell::Error, picked_commit: String },
+    #[error("failed to read the unmerged paths")]
+    GitUnmergedPathsReadFailed { source: xshell::Error },
+    #[error("failed to resolve the conflicts in the picked paths")]
+    ResolveConflictsToTheirsFailed { source: MergeCommandResolveConflictsToTheirsError },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{commit_test_file, new_test_repo};
+
+    fn to_parents(pairs: &[(&str, Vec<&str>)]) -> Vec<(String, Vec<String>)> {
+        pairs
+            .iter()
+            .map(|(remote, extends)| (remote.to_string(), extends.iter().map(ToString::to_string).collect()))
+            .collect()
+    }
+
+    #[test]
+    fn must_detect_a_cycle_between_two_templates() {
+        let parents = to_parents(&[
+            ("repoconf-a", vec!["repoconf-b"]),
+            ("repoconf-b", vec!["repoconf-a"]),
+        ]);
+        let error = MergeCommand::template_layer(&parents, "repoconf-a", 0).unwrap_err();
+        assert!(matches!(error, MergeCommandTemplateLayerError::CycleDetected { .. }));
+    }
+
+    #[test]
+    fn must_collect_the_transitive_ancestors() {
+        let parents = to_parents(&[
+            ("repoconf-a", vec!["repoconf-b"]),
+            ("repoconf-b", vec!["repoconf-c"]),
+            ("repoconf-c", vec![]),
+        ]);
+        let layer = MergeCommand::template_layer(&parents, "repoconf-a", 0).unwrap();
+        assert_eq!(layer.ancestors, vec!["repoconf-b".to_string(), "repoconf-c".to_string()]);
+    }
+
+    #[test]
+    fn must_forget_the_picks_merged_from_a_template_subdirectory() {
+        let sh_template = new_test_repo("forget-picks-template");
+        commit_test_file(&sh_template, "sub/a.txt", "1");
+        let picked_commit = commit_test_file(&sh_template, "sub/a.txt", "2");
+        let template_dir = sh_template.current_dir();
+
+        let sh_child = new_test_repo("forget-picks-child");
+        commit_test_file(&sh_child, "README.md", "child");
+        cmd!(sh_child, "git remote add repoconf-t {template_dir}")
+            .run()
+            .unwrap();
+        cmd!(sh_child, "git fetch --quiet repoconf-t")
+            .run()
+            .unwrap();
+        TemplateLock::add_picked(&sh_child, "repoconf-t", &[picked_commit.clone()]).unwrap();
+
+        // Older versions of `git subtree split` require the prefix to exist in the working tree
+        sh_child.create_dir("sub").unwrap();
+        // The split history of the subdirectory has different commits, so it never contains the picked commit
+        let split_ref = MergeCommand::resolve_merged_ref(&sh_child, "repoconf-t", "main", Some("sub")).unwrap();
+        assert!(
+            !sh_child
+                .git_is_ancestor(&picked_commit, &split_ref)
+                .unwrap()
+        );
+
+        let is_lock_changed = MergeCommand::forget_merged_picks(&sh_child, "repoconf-t", "repoconf-t/main").unwrap();
+        assert!(is_lock_changed);
+        assert_eq!(TemplateLock::picked(&sh_child, "repoconf-t").unwrap(), Vec::<String>::new());
+    }
 }
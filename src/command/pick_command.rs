@@ -0,0 +1,242 @@
+use crate::{BranchNameStrategy, BranchNameStrategyToBranchNameError, GitLocalBranchExists, GitLocalBranchExistsError, GitRefsError, GitRemoteNames, GitRemoteNamesError, HookOptions, HookPhase, HookRunnerRunError, HookRunnerWithTemplateError, IsCleanRepo, IsCleanRepoError, MergeCommand, MergeCommandForgetMergeStateError, MergeCommandRecordMergeResultError, MergeCommandRecordMergeStateError, MergeCommandRestorePreviousHeadError, REPOCONF_PRE_MERGE_REF, REPOCONF_SPLIT_REF_PREFIX, TemplateConfig, TemplateConfigLoadError, TemplateLock, TemplateLockAddPickedError, UnixTimestampError, UnwrapOrCurrentDirError, git_refs, unix_timestamp, unwrap_or_current_dir};
+use clap::{Parser, value_parser};
+use errgonomic::{ErrVec, handle, handle_bool, handle_iter};
+use itertools::Itertools;
+use std::path::PathBuf;
+use std::process::ExitCode;
+use thiserror::Error;
+use xshell::{Shell, cmd};
+
+#[derive(Parser, Clone, Debug)]
+pub struct PickCommand {
+    /// Child repository directory (defaults to current directory)
+    #[arg(long, short, value_parser = value_parser!(PathBuf))]
+    pub dir: Option<PathBuf>,
+
+    /// Do not push picked commits after picking
+    #[arg(long)]
+    pub no_push: bool,
+
+    /// Do not update the template remote before picking
+    #[arg(long)]
+    pub no_remote_update: bool,
+
+    /// Do not run the post-merge hook after picking
+    #[arg(long)]
+    pub skip_post_merge: bool,
+
+    /// Stay on the local branch after picking instead of switching back to the branch (or commit) that was checked out before picking
+    #[arg(long)]
+    pub stay: bool,
+
+    /// Name of the local branch to pick onto
+    ///
+    /// If you pass "-", the command will determine the branch automatically: use "main" if exists, use "master" if exists.
+    ///
+    /// If the local branch doesn't exist, the command will exit with an error
+    #[arg(long = "local-branch", short = 'l', default_value = "-")]
+    pub local_branch_strategy: BranchNameStrategy,
+
+    /// Template remote name (e.g. "repoconf-rust-lib") or template name (e.g. "rust-lib")
+    #[arg()]
+    pub template: String,
+
+    /// Commits of the template remote to cherry-pick (e.g. "repoconf-rust-lib/main~2" or a commit hash)
+    #[arg(required = true, num_args = 1..)]
+    pub commits: Vec<String>,
+
+    #[command(flatten)]
+    pub hook_options: HookOptions,
+}
+
+impl PickCommand {
+    pub async fn run(self) -> Result<ExitCode, PickCommandRunError> {
+        use PickCommandRunError::*;
+        let Self {
+            dir,
+            no_push,
+            no_remote_update,
+            skip_post_merge,
+            stay,
+            local_branch_strategy,
+            template,
+            commits,
+            hook_options,
+        } = self;
+
+        let dir = handle!(unwrap_or_current_dir(dir), UnwrapOrCurrentDirFailed);
+        let sh_dir = handle!(Shell::new(), ShellNewFailed).with_current_dir(&dir);
+
+        let remote = if template.starts_with("repoconf-") { template } else { format!("repoconf-{template}") };
+        let remote_exists = handle!(sh_dir.git_remote_names(), GitRemoteNamesFailed).any(|name| name == remote);
+        handle_bool!(!remote_exists, RemoteNotFound, remote);
+
+        let is_clean = handle!(sh_dir.is_clean_repo(), IsCleanRepoFailed);
+        handle_bool!(!is_clean, RepositoryNotClean, dir);
+
+        if !no_remote_update {
+            handle!(cmd!(sh_dir, "git remote update {remote}").run_echo(), GitRemoteUpdateFailed, remote);
+        }
+
+        let picked_commits = handle_iter!(
+            commits
+                .iter()
+                .map(|commit| Self::resolve_commit(&sh_dir, &remote, commit)),
+            ResolveCommitsFailed
+        );
+
+        let refs = handle!(git_refs(&sh_dir), GitRefsFailed);
+        let local_branch_name = handle!(
+            local_branch_strategy.to_branch_name("refs/heads", &refs),
+            LocalBranchNameResolveFailed,
+            prefix: "refs/heads",
+            strategy: local_branch_strategy
+        );
+        let local_branch_exists = handle!(
+            sh_dir.git_local_branch_exists(&local_branch_name),
+            GitLocalBranchExistsFailed,
+            branch_name: local_branch_name
+        );
+        handle_bool!(!local_branch_exists, LocalBranchDoesNotExist, branch_name: local_branch_name);
+
+        let backup_timestamp = handle!(unix_timestamp(), UnixTimestampFailed);
+        handle!(MergeCommand::record_merge_state(&sh_dir, &local_branch_name, backup_timestamp), RecordMergeStateFailed);
+        handle!(cmd!(sh_dir, "git checkout {local_branch_name}").run_echo(), GitCheckoutFailed, branch_name: local_branch_name);
+
+        let TemplateConfig {
+            prefix,
+            ..
+        } = handle!(TemplateConfig::load(&sh_dir, &remote), TemplateConfigLoadFailed, remote);
+        // `-x` appends the original commit hash to the commit message, so the origin of the picked commit stays visible in the history
+        // `-Xsubtree` maps the template paths onto the subdirectory that the template is merged into
+        let flags = ["-x".to_string()]
+            .into_iter()
+            .chain(prefix.map(|prefix| format!("-Xsubtree={prefix}")))
+            .collect_vec();
+        let picked_commits_slice = picked_commits.as_slice();
+        if let Err(source) = cmd!(sh_dir, "git cherry-pick {flags...} {picked_commits_slice...}").run_echo() {
+            let cherry_pick_head_path = handle!(cmd!(sh_dir, "git rev-parse --path-format=absolute --git-path CHERRY_PICK_HEAD").read(), GitCherryPickHeadPathFailed);
+            // The staged lock is committed together with the conflicting commit by `git cherry-pick --continue`
+            if sh_dir.path_exists(cherry_pick_head_path) {
+                handle!(TemplateLock::add_picked(&sh_dir, &remote, &picked_commits), TemplateLockAddPickedFailed, remote);
+            }
+            return Err(GitCherryPickFailed {
+                source,
+                picked_commits,
+            });
+        }
+
+        handle!(TemplateLock::add_picked(&sh_dir, &remote, &picked_commits), TemplateLockAddPickedFailed, remote);
+        let message = format!("Lock the commits picked from {remote}");
+        handle!(cmd!(sh_dir, "git commit -m {message}").run_echo(), GitCommitLockFailed);
+
+        let pre_merge_ref = REPOCONF_PRE_MERGE_REF;
+        let pre_merge_commit = handle!(cmd!(sh_dir, "git rev-parse {pre_merge_ref}").read(), GitPreMergeCommitReadFailed);
+        let picked_range = format!("{pre_merge_commit}..HEAD");
+        let hook_runner = handle!(hook_options.hook_runner().with_template(&sh_dir, &remote), HookRunnerWithTemplateFailed, remote)
+            .with_env("REPOCONF_COMMAND", "pick")
+            .with_env("REPOCONF_NO_PUSH", if no_push { "1" } else { "0" })
+            .with_env("REPOCONF_LOCAL_BRANCH", &local_branch_name)
+            .with_env("REPOCONF_MERGED_RANGE", picked_range);
+        if !skip_post_merge {
+            handle!(hook_runner.run(&sh_dir, HookPhase::PostMerge), RunHooksFailed, phase: HookPhase::PostMerge);
+        }
+
+        if !no_push {
+            handle!(hook_runner.run(&sh_dir, HookPhase::PrePush), RunHooksFailed, phase: HookPhase::PrePush);
+        }
+
+        handle!(MergeCommand::record_merge_result(&sh_dir, &sh_dir), RecordMergeResultFailed);
+
+        if !no_push {
+            handle!(cmd!(sh_dir, "git push").run_echo(), GitPushFailed);
+            handle!(MergeCommand::forget_merge_state(&sh_dir), ForgetMergeStateFailed);
+        }
+
+        if !stay {
+            handle!(MergeCommand::restore_previous_head(&sh_dir), RestorePreviousHeadFailed);
+        }
+
+        Ok(ExitCode::SUCCESS)
+    }
+
+    /// Resolves a commit-ish to a commit hash and ensures that the commit belongs to the template remote
+    fn resolve_commit(sh_dir: &Shell, remote: &str, commit: &str) -> Result<String, PickCommandResolveCommitError> {
+        use PickCommandResolveCommitError::*;
+        let commit_spec = format!("{commit}^{{commit}}");
+        let commit_hash = handle!(cmd!(sh_dir, "git rev-parse --verify {commit_spec}").read(), GitRevParseFailed, commit);
+        let remote_refs_prefix = format!("refs/remotes/{remote}/");
+        let split_refs_prefix = format!("{REPOCONF_SPLIT_REF_PREFIX}/{remote}/");
+        let containing_refs = handle!(cmd!(sh_dir, "git for-each-ref --contains {commit_hash} --format='%(refname)' {remote_refs_prefix} {split_refs_prefix}").read(), GitForEachRefFailed, commit);
+        handle_bool!(containing_refs.is_empty(), CommitNotInRemote, commit, remote);
+        Ok(commit_hash)
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum PickCommandRunError {
+    #[error("failed to resolve the target directory")]
+    UnwrapOrCurrentDirFailed { source: UnwrapOrCurrentDirError },
+    #[error("failed to create a shell instance")]
+    ShellNewFailed { source: xshell::Error },
+    #[error("failed to read git remote names")]
+    GitRemoteNamesFailed { source: GitRemoteNamesError },
+    #[error("template remote '{remote}' does not exist")]
+    RemoteNotFound { remote: String },
+    #[error("failed to check repository status")]
+    IsCleanRepoFailed { source: IsCleanRepoError },
+    #[error("repository '{dir}' has uncommitted changes")]
+    RepositoryNotClean { dir: PathBuf },
+    #[error("failed to update template remote '{remote}'")]
+    GitRemoteUpdateFailed { source: xshell::Error, remote: String },
+    #[error("failed to resolve {len} commits", len = source.len())]
+    ResolveCommitsFailed { source: ErrVec<PickCommandResolveCommitError> },
+    #[error("failed to read git refs")]
+    GitRefsFailed { source: GitRefsError },
+    #[error("failed to resolve local branch name for prefix '{prefix}'")]
+    LocalBranchNameResolveFailed { source: BranchNameStrategyToBranchNameError, prefix: String, strategy: BranchNameStrategy },
+    #[error("failed to check whether local branch '{branch_name}' exists")]
+    GitLocalBranchExistsFailed { source: GitLocalBranchExistsError, branch_name: String },
+    #[error("local branch '{branch_name}' does not exist")]
+    LocalBranchDoesNotExist { branch_name: String },
+    #[error("failed to compute the backup timestamp")]
+    UnixTimestampFailed { source: UnixTimestampError },
+    #[error("failed to record the merge state")]
+    RecordMergeStateFailed { source: MergeCommandRecordMergeStateError },
+    #[error("failed to check out local branch '{branch_name}'")]
+    GitCheckoutFailed { source: xshell::Error, branch_name: String },
+    #[error("failed to load the config of template '{remote}'")]
+    TemplateConfigLoadFailed { source: TemplateConfigLoadError, remote: String },
+    #[error("failed to resolve the cherry-pick state path")]
+    GitCherryPickHeadPathFailed { source: xshell::Error },
+    #[error("failed to cherry-pick {picked_commits:?}; if the cherry-pick stopped on conflicts, resolve them and run `git cherry-pick --continue` (the picked commits have been staged in the template lock)")]
+    GitCherryPickFailed { source: xshell::Error, picked_commits: Vec<String> },
+    #[error("failed to record the commits picked from '{remote}' in the template lock")]
+    TemplateLockAddPickedFailed { source: TemplateLockAddPickedError, remote: String },
+    #[error("failed to commit the template lock")]
+    GitCommitLockFailed { source: xshell::Error },
+    #[error("failed to read the commit before the pick")]
+    GitPreMergeCommitReadFailed { source: xshell::Error },
+    #[error("failed to prepare the hook environment of template remote '{remote}'")]
+    HookRunnerWithTemplateFailed { source: HookRunnerWithTemplateError, remote: String },
+    #[error("failed to run the {phase} hooks")]
+    RunHooksFailed { source: HookRunnerRunError, phase: HookPhase },
+    #[error("failed to record the pick result")]
+    RecordMergeResultFailed { source: MergeCommandRecordMergeResultError },
+    #[error("failed to push picked commits")]
+    GitPushFailed { source: xshell::Error },
+    #[error("failed to forget the state of the pushed pick")]
+    ForgetMergeStateFailed { source: MergeCommandForgetMergeStateError },
+    #[error("failed to switch back to the previously checked out branch")]
+    RestorePreviousHeadFailed { source: MergeCommandRestorePreviousHeadError },
+}
+
+#[derive(Error, Debug)]
+pub enum PickCommandResolveCommitError {
+    #[error("failed to resolve commit '{commit}'")]
+    GitRevParseFailed { source: xshell::Error, commit: String },
+    #[error("failed to find the refs that contain commit '{commit}'")]
+    GitForEachRefFailed { source: xshell::Error, commit: String },
+    #[error("commit '{commit}' does not belong to template remote '{remote}'")]
+    CommitNotInRemote { commit: String, remote: String },
+}
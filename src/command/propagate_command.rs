@@ -1,12 +1,14 @@
-use crate::{BranchNameStrategy, MergeCommand, MergeCommandRunError};
+use crate::{BranchNameStrategy, HookOptions, HookPhase, HookRunnerRunError, MergeCommand, MergeCommandRunError, UnixTimestampError, unix_timestamp};
 use clap::{Parser, value_parser};
 use errgonomic::{ErrVec, handle, handle_iter, map_err};
 use futures::stream::{self, TryStreamExt};
 use itertools::Itertools;
+use std::iter::once;
 use std::path::PathBuf;
 use std::process::ExitCode;
 use thiserror::Error;
 use walkdir::WalkDir;
+use xshell::Shell;
 
 #[derive(Parser, Clone, Debug)]
 pub struct PropagateCommand {
@@ -29,6 +31,9 @@ pub struct PropagateCommand {
     /// Directory to search in, recursively
     #[arg(value_parser = value_parser!(PathBuf))]
     pub dir: PathBuf,
+
+    #[command(flatten)]
+    pub hook_options: HookOptions,
 }
 
 impl PropagateCommand {
@@ -38,10 +43,28 @@ impl PropagateCommand {
             local_branch_name,
             remote_branch_name,
             dir,
+            hook_options,
         } = self;
 
         let repos = handle!(Self::collect_repos(&dir), CollectReposFailed, dir);
-        handle!(Self::merge_repos(repos, local_branch_name, remote_branch_name).await, MergeReposFailed);
+        let backup_timestamp = handle!(unix_timestamp(), UnixTimestampFailed);
+        let failed_repos = handle!(Self::merge_repos(repos, local_branch_name, remote_branch_name, backup_timestamp, &hook_options).await, MergeReposFailed);
+        if !failed_repos.is_empty() {
+            // The post-propagate hooks concern a complete run, so they are skipped if any repository has failed
+            eprintln!("[WARN] The merge has failed in {len} repositories:", len = failed_repos.len());
+            failed_repos
+                .iter()
+                .for_each(|repo| eprintln!("[FAILED] {}", repo.display()));
+            return Ok(ExitCode::FAILURE);
+        }
+
+        // The post-propagate hooks are discovered in the search directory, because they concern all repositories of the run
+        let sh_dir = handle!(Shell::new(), ShellNewFailed).with_current_dir(&dir);
+        let hook_runner = hook_options
+            .hook_runner()
+            .with_env("REPOCONF_COMMAND", "propagate")
+            .with_env("REPOCONF_NO_PUSH", "0");
+        handle!(hook_runner.run(&sh_dir, HookPhase::PostPropagate), RunHooksFailed);
 
         Ok(ExitCode::SUCCESS)
     }
@@ -57,22 +80,32 @@ impl PropagateCommand {
         Ok(repos)
     }
 
-    async fn merge_repos(repos: Vec<PathBuf>, local_branch_name: BranchNameStrategy, remote_branch_name: BranchNameStrategy) -> Result<(), PropagateCommandMergeReposError> {
+    /// Merges the templates into every repository and returns the repositories where the merge command has exited with a failure code (e.g. because of a failed verification)
+    async fn merge_repos(repos: Vec<PathBuf>, local_branch_name: BranchNameStrategy, remote_branch_name: BranchNameStrategy, backup_timestamp: u64, hook_options: &HookOptions) -> Result<Vec<PathBuf>, PropagateCommandMergeReposError> {
         use PropagateCommandMergeReposError::*;
+        let local_branch_name = &local_branch_name;
+        let remote_branch_name = &remote_branch_name;
         stream::iter(
             repos
                 .into_iter()
                 .map(Ok::<_, PropagateCommandMergeReposError>),
         )
-        .try_for_each(|repo| async {
+        .try_fold(Vec::<PathBuf>::new(), |failed_repos, repo| async move {
             println!("Entering {}", repo.display());
             let merge_command = MergeCommand {
                 local_branch_strategy: local_branch_name.clone(),
                 remote_branch_strategy: remote_branch_name.clone(),
-                dir: Some(repo),
+                backup_timestamp: Some(backup_timestamp),
+                dir: Some(repo.clone()),
+                hook_options: hook_options.clone(),
                 ..MergeCommand::default()
             };
-            map_err!(merge_command.run().await, MergeCommandRunFailed).map(|_| ())
+            let exit_code = map_err!(merge_command.run().await, MergeCommandRunFailed)?;
+            if exit_code == ExitCode::SUCCESS {
+                Ok(failed_repos)
+            } else {
+                Ok(failed_repos.into_iter().chain(once(repo)).collect())
+            }
         })
         .await
     }
@@ -82,8 +115,14 @@ impl PropagateCommand {
 pub enum PropagateCommandRunError {
     #[error("failed to discover repositories under '{dir}'")]
     CollectReposFailed { source: PropagateCommandCollectReposError, dir: PathBuf },
+    #[error("failed to compute the backup timestamp")]
+    UnixTimestampFailed { source: UnixTimestampError },
     #[error("failed to merge discovered repositories")]
     MergeReposFailed { source: PropagateCommandMergeReposError },
+    #[error("failed to create a shell instance")]
+    ShellNewFailed { source: xshell::Error },
+    #[error("failed to run the post-propagate hooks")]
+    RunHooksFailed { source: HookRunnerRunError },
 }
 
 #[derive(Error, Debug)]
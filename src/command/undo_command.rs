@@ -0,0 +1,120 @@
+use crate::{GitIsAncestor, GitIsAncestorError, REPOCONF_MERGE_BRANCH_REF, REPOCONF_POST_MERGE_REF, REPOCONF_PRE_MERGE_REF, UnwrapOrCurrentDirError, unwrap_or_current_dir};
+use clap::{Parser, value_parser};
+use errgonomic::{handle, handle_bool};
+use std::path::PathBuf;
+use std::process::ExitCode;
+use thiserror::Error;
+use xshell::{Shell, cmd};
+
+#[derive(Parser, Clone, Debug)]
+pub struct UndoCommand {
+    /// Child repository directory (defaults to current directory)
+    #[arg(long, short, value_parser = value_parser!(PathBuf))]
+    pub dir: Option<PathBuf>,
+}
+
+impl UndoCommand {
+    /// PRUNING: Removes the commits of the last template merge from the local branch, because the user has requested to undo the merge. The removed commits remain reachable via the reflog.
+    pub async fn run(self) -> Result<ExitCode, UndoCommandRunError> {
+        use UndoCommandRunError::*;
+        let Self {
+            dir,
+        } = self;
+
+        let dir = handle!(unwrap_or_current_dir(dir), UnwrapOrCurrentDirFailed);
+        let sh_dir = handle!(Shell::new(), ShellNewFailed).with_current_dir(&dir);
+
+        let pre_merge_ref = REPOCONF_PRE_MERGE_REF;
+        let post_merge_ref = REPOCONF_POST_MERGE_REF;
+        let merge_branch_ref = REPOCONF_MERGE_BRANCH_REF;
+        let pre_merge_commit = handle!(
+            cmd!(sh_dir, "git rev-parse --verify --quiet {pre_merge_ref}")
+                .ignore_status()
+                .read(),
+            GitPreMergeCommitReadFailed
+        );
+        handle_bool!(pre_merge_commit.is_empty(), MergeNotFound, dir);
+        let post_merge_commit = handle!(
+            cmd!(sh_dir, "git rev-parse --verify --quiet {post_merge_ref}")
+                .ignore_status()
+                .read(),
+            GitPostMergeCommitReadFailed
+        );
+        handle_bool!(post_merge_commit.is_empty(), MergeNotFinished, dir);
+
+        let local_branch_ref = handle!(cmd!(sh_dir, "git symbolic-ref {merge_branch_ref}").read(), GitMergeBranchReadFailed);
+        let local_branch_commit = handle!(cmd!(sh_dir, "git rev-parse {local_branch_ref}").read(), GitLocalBranchCommitReadFailed, local_branch_ref);
+        handle_bool!(local_branch_commit == pre_merge_commit, NothingToUndo, local_branch_ref);
+
+        // The commits made after the merge would be discarded by the reset
+        handle_bool!(local_branch_commit != post_merge_commit, LocalBranchMoved, local_branch_ref, post_merge_commit);
+
+        let upstream = format!("{local_branch_ref}@{{upstream}}");
+        let upstream_commit = handle!(
+            cmd!(sh_dir, "git rev-parse --verify --quiet {upstream}")
+                .ignore_status()
+                .read(),
+            GitUpstreamCommitReadFailed,
+            local_branch_ref
+        );
+        if !upstream_commit.is_empty() {
+            let is_pushed = handle!(sh_dir.git_is_ancestor(&local_branch_commit, &upstream_commit), GitIsAncestorFailed);
+            handle_bool!(is_pushed, MergeAlreadyPushed, local_branch_ref);
+        }
+
+        let current_ref = handle!(
+            cmd!(sh_dir, "git symbolic-ref --quiet HEAD")
+                .ignore_status()
+                .read(),
+            GitCurrentRefReadFailed
+        );
+        if current_ref == local_branch_ref {
+            // `--keep` refuses to reset if it would overwrite uncommitted changes
+            handle!(cmd!(sh_dir, "git reset --keep {pre_merge_commit}").run_echo(), GitResetFailed, local_branch_ref, pre_merge_commit);
+        } else {
+            handle!(cmd!(sh_dir, "git update-ref {local_branch_ref} {pre_merge_commit} {local_branch_commit}").run_echo(), GitUpdateRefFailed, local_branch_ref, pre_merge_commit);
+        }
+        handle!(cmd!(sh_dir, "git update-ref -d {pre_merge_ref}").run_echo(), GitUpdateRefDeleteFailed, git_ref: pre_merge_ref);
+        handle!(cmd!(sh_dir, "git update-ref -d {post_merge_ref}").run_echo(), GitUpdateRefDeleteFailed, git_ref: post_merge_ref);
+
+        Ok(ExitCode::SUCCESS)
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum UndoCommandRunError {
+    #[error("failed to resolve the target directory")]
+    UnwrapOrCurrentDirFailed { source: UnwrapOrCurrentDirError },
+    #[error("failed to create a shell instance")]
+    ShellNewFailed { source: xshell::Error },
+    #[error("failed to read the pre-merge commit")]
+    GitPreMergeCommitReadFailed { source: xshell::Error },
+    #[error("repository '{dir}' has no template merge to undo")]
+    MergeNotFound { dir: PathBuf },
+    #[error("failed to read the post-merge commit")]
+    GitPostMergeCommitReadFailed { source: xshell::Error },
+    #[error("the last template merge in repository '{dir}' hasn't finished (run `repoconf merge --abort` to abort it)")]
+    MergeNotFinished { dir: PathBuf },
+    #[error("failed to read the merge branch")]
+    GitMergeBranchReadFailed { source: xshell::Error },
+    #[error("failed to read the commit of '{local_branch_ref}'")]
+    GitLocalBranchCommitReadFailed { source: xshell::Error, local_branch_ref: String },
+    #[error("the last template merge didn't add any commits to '{local_branch_ref}'")]
+    NothingToUndo { local_branch_ref: String },
+    #[error("'{local_branch_ref}' has moved since the template merge (expected '{post_merge_commit}'); undoing the merge would discard the newer commits")]
+    LocalBranchMoved { local_branch_ref: String, post_merge_commit: String },
+    #[error("failed to check whether the merge has been pushed")]
+    GitIsAncestorFailed { source: GitIsAncestorError },
+    #[error("failed to read the upstream commit of '{local_branch_ref}'")]
+    GitUpstreamCommitReadFailed { source: xshell::Error, local_branch_ref: String },
+    #[error("the template merge on '{local_branch_ref}' has already been pushed")]
+    MergeAlreadyPushed { local_branch_ref: String },
+    #[error("failed to read the current branch")]
+    GitCurrentRefReadFailed { source: xshell::Error },
+    #[error("failed to reset '{local_branch_ref}' to '{pre_merge_commit}'")]
+    GitResetFailed { source: xshell::Error, local_branch_ref: String, pre_merge_commit: String },
+    #[error("failed to move '{local_branch_ref}' to '{pre_merge_commit}'")]
+    GitUpdateRefFailed { source: xshell::Error, local_branch_ref: String, pre_merge_commit: String },
+    #[error("failed to delete '{git_ref}'")]
+    GitUpdateRefDeleteFailed { source: xshell::Error, git_ref: String },
+}
@@ -0,0 +1,14 @@
+/// A symbolic ref that points to the local branch of the last template merge
+pub const REPOCONF_MERGE_BRANCH_REF: &str = "refs/repoconf/merge-branch";
+
+/// A ref that points to the commit of the local branch before the last template merge
+pub const REPOCONF_PRE_MERGE_REF: &str = "refs/repoconf/pre-merge";
+
+/// A ref that points to the commit of the local branch after the last template merge (recorded before pushing), so that the merge is undone only if the branch hasn't moved since
+pub const REPOCONF_POST_MERGE_REF: &str = "refs/repoconf/post-merge";
+
+/// A ref namespace for the backups of the local branch (one ref per template merge, named after the Unix timestamp of the merge)
+pub const REPOCONF_BACKUP_REF_PREFIX: &str = "refs/repoconf/backup";
+
+/// A ref namespace for the histories of the template subdirectories (one ref per template remote and branch, see `<url>#<branch>:<path>`)
+pub const REPOCONF_SPLIT_REF_PREFIX: &str = "refs/repoconf/split";
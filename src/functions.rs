@@ -13,3 +13,17 @@ pub use unwrap_or_current_dir::*;
 mod git_refs;
 
 pub use git_refs::*;
+
+mod unix_timestamp;
+
+pub use unix_timestamp::*;
+
+mod template_config_key;
+
+pub use template_config_key::*;
+
+#[cfg(test)]
+mod test_repo;
+
+#[cfg(test)]
+pub use test_repo::*;
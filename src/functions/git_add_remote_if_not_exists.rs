@@ -1,17 +1,27 @@
 use crate::{ConvertStrToGitRemoteError, GitRemote};
-use errgonomic::{ErrVec, handle, handle_iter};
+use errgonomic::{ErrVec, handle, handle_bool, handle_iter};
 use thiserror::Error;
 use xshell::{Shell, cmd};
 
-pub fn git_remote_exists(sh: &Shell, remote_template_url: &str) -> Result<bool, GitRemoteExistsError> {
+/// Returns true if the remote named `remote_name` exists and points to `remote_url`, false if it doesn't exist
+///
+/// The remotes are matched by name, because several templates may come from the same URL (e.g. different subdirectories of a template monorepo)
+pub fn git_remote_exists(sh: &Shell, remote_name: &str, remote_url: &str) -> Result<bool, GitRemoteExistsError> {
     use GitRemoteExistsError::*;
     let output = handle!(cmd!(sh, "git remote -v").read(), ReadRemotesFailed);
     let results = output.lines().map(GitRemote::try_from);
     let remotes: Vec<GitRemote> = handle_iter!(results, ParseRemotesFailed);
-    let remote_exists = remotes
+    let remote_urls = remotes
         .iter()
-        .any(|remote| remote.url == remote_template_url);
-    Ok(remote_exists)
+        .filter(|remote| remote.name == remote_name)
+        .map(|remote| remote.url.as_str())
+        .collect::<Vec<_>>();
+    if remote_urls.is_empty() {
+        return Ok(false);
+    }
+    let actual_urls = remote_urls.join(", ");
+    handle_bool!(!remote_urls.contains(&remote_url), RemoteUrlMismatch, remote_name, remote_url, actual_urls);
+    Ok(true)
 }
 
 #[derive(Error, Debug)]
@@ -20,4 +30,6 @@ pub enum GitRemoteExistsError {
     ReadRemotesFailed { source: xshell::Error },
     #[error("failed to parse git remotes")]
     ParseRemotesFailed { source: ErrVec<ConvertStrToGitRemoteError> },
+    #[error("remote '{remote_name}' already exists with URL '{actual_urls}' instead of '{remote_url}'")]
+    RemoteUrlMismatch { remote_name: String, remote_url: String, actual_urls: String },
 }
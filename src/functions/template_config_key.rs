@@ -0,0 +1,4 @@
+/// Returns the git config key for a per-template setting (e.g. `repoconf.repoconf-rust-lib.prefix`)
+pub fn template_config_key(remote: &str, name: &str) -> String {
+    format!("repoconf.{remote}.{name}")
+}
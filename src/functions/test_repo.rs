@@ -0,0 +1,36 @@
+use std::env::temp_dir;
+use std::fs::write;
+use std::path::Path;
+use std::process::id;
+use xshell::{Shell, cmd};
+
+/// Creates an empty git repository with the `main` branch in a fresh temporary directory and returns a shell in this directory (tests only)
+pub fn new_test_repo(name: &str) -> Shell {
+    let dir = temp_dir().join(format!("repoconf-test-{name}-{pid}", pid = id()));
+    let sh = Shell::new().unwrap().with_current_dir(&dir);
+    sh.remove_path(&dir).unwrap();
+    sh.create_dir(&dir).unwrap();
+    cmd!(sh, "git init --quiet --initial-branch=main")
+        .run()
+        .unwrap();
+    cmd!(sh, "git config user.name repoconf").run().unwrap();
+    cmd!(sh, "git config user.email repoconf@example.com")
+        .run()
+        .unwrap();
+    sh
+}
+
+/// Writes the `contents` to the `path` in the repository of `sh`, commits it and returns the commit (tests only)
+pub fn commit_test_file(sh: &Shell, path: &str, contents: &str) -> String {
+    let file_path = sh.current_dir().join(path);
+    if let Some(parent) = Path::new(path)
+        .parent()
+        .filter(|parent| !parent.as_os_str().is_empty())
+    {
+        sh.create_dir(parent).unwrap();
+    }
+    write(&file_path, contents).unwrap();
+    cmd!(sh, "git add -- {path}").run().unwrap();
+    cmd!(sh, "git commit --quiet -m {path}").run().unwrap();
+    cmd!(sh, "git rev-parse HEAD").read().unwrap()
+}
@@ -0,0 +1,15 @@
+use errgonomic::handle;
+use std::time::{SystemTime, SystemTimeError, UNIX_EPOCH};
+use thiserror::Error;
+
+pub fn unix_timestamp() -> Result<u64, UnixTimestampError> {
+    use UnixTimestampError::*;
+    let duration = handle!(SystemTime::now().duration_since(UNIX_EPOCH), DurationSinceFailed);
+    Ok(duration.as_secs())
+}
+
+#[derive(Error, Debug)]
+pub enum UnixTimestampError {
+    #[error("failed to compute the current Unix timestamp")]
+    DurationSinceFailed { source: SystemTimeError },
+}
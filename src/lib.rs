@@ -3,6 +3,10 @@
 
 use tokio as _;
 
+mod constants;
+
+pub use constants::*;
+
 mod types;
 
 pub use types::*;
@@ -15,3 +15,11 @@ mod repo_name;
 
 pub use git_branch_exists::*;
 pub use repo_name::*;
+
+mod git_is_ancestor;
+
+pub use git_is_ancestor::*;
+
+mod git_config_get_all;
+
+pub use git_config_get_all::*;
@@ -0,0 +1,33 @@
+use errgonomic::{handle, handle_bool};
+use std::io;
+use std::process::Output;
+use thiserror::Error;
+use xshell::{Shell, cmd};
+
+pub trait GitConfigGetAll {
+    /// Returns all values of a multi-valued git config key (or an empty vec if the key is not set)
+    fn git_config_get_all(&self, key: &str) -> Result<Vec<String>, GitConfigGetAllError>;
+}
+
+impl GitConfigGetAll for Shell {
+    fn git_config_get_all(&self, key: &str) -> Result<Vec<String>, GitConfigGetAllError> {
+        use GitConfigGetAllError::*;
+        let output = handle!(cmd!(self, "git config --get-all {key}").to_command().output(), OutputFailed, key: key);
+        // `git config --get-all` exits with 1 if the key is not set
+        let is_expected = output.status.success() || output.status.code() == Some(1);
+        handle_bool!(!is_expected, UnexpectedOutput, key: key, output);
+        let values = String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .map(ToOwned::to_owned)
+            .collect();
+        Ok(values)
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum GitConfigGetAllError {
+    #[error("failed to read git config key '{key}'")]
+    OutputFailed { source: io::Error, key: String },
+    #[error("unexpected output while reading git config key '{key}'")]
+    UnexpectedOutput { key: String, output: Output },
+}
@@ -0,0 +1,29 @@
+use errgonomic::handle;
+use std::io;
+use thiserror::Error;
+use xshell::{Shell, cmd};
+
+pub trait GitIsAncestor {
+    fn git_is_ancestor(&self, ancestor: &str, descendant: &str) -> Result<bool, GitIsAncestorError>;
+}
+
+impl GitIsAncestor for Shell {
+    fn git_is_ancestor(&self, ancestor: &str, descendant: &str) -> Result<bool, GitIsAncestorError> {
+        use GitIsAncestorError::*;
+        let output = handle!(
+            cmd!(self, "git merge-base --is-ancestor {ancestor} {descendant}")
+                .to_command()
+                .status(),
+            StatusFailed,
+            ancestor: ancestor,
+            descendant: descendant
+        );
+        Ok(output.success())
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum GitIsAncestorError {
+    #[error("failed to check whether '{ancestor}' is an ancestor of '{descendant}'")]
+    StatusFailed { source: io::Error, ancestor: String, descendant: String },
+}
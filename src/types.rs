@@ -14,3 +14,35 @@ mod branch_name_strategy_value_parser;
 pub use branch_name_strategy_value_parser::*;
 mod git_branch_name;
 pub use git_branch_name::*;
+mod rebase_branches_mode;
+pub use rebase_branches_mode::*;
+mod template_config;
+pub use template_config::*;
+mod template_ref;
+pub use template_ref::*;
+mod sync_mode;
+pub use sync_mode::*;
+mod index_entry;
+pub use index_entry::*;
+mod template_layer;
+pub use template_layer::*;
+mod touched_path;
+pub use touched_path::*;
+mod hook_phase;
+pub use hook_phase::*;
+mod hook_runner;
+pub use hook_runner::*;
+mod hook_options;
+pub use hook_options::*;
+mod hook_trust_store;
+pub use hook_trust_store::*;
+mod verify_failure_mode;
+pub use verify_failure_mode::*;
+mod provisioner;
+pub use provisioner::*;
+mod template_variable;
+pub use template_variable::*;
+mod template_lock;
+pub use template_lock::*;
+mod merge_context;
+pub use merge_context::*;
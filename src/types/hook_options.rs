@@ -0,0 +1,25 @@
+use crate::HookRunner;
+use clap::Args;
+use std::time::Duration;
+
+/// Command-line options that control how the hooks are run
+#[derive(Args, Default, Eq, PartialEq, Hash, Clone, Debug)]
+pub struct HookOptions {
+    /// Run the hooks without a terminal: close their stdin, set `CI=1` and capture their output (the captured output is attached to the error if a hook fails)
+    #[arg(long)]
+    pub non_interactive: bool,
+
+    /// Kill a hook that runs longer than this number of seconds
+    #[arg(long, value_name = "SECONDS")]
+    pub hook_timeout: Option<u64>,
+}
+
+impl HookOptions {
+    pub fn hook_runner(&self) -> HookRunner {
+        HookRunner {
+            non_interactive: self.non_interactive,
+            timeout: self.hook_timeout.map(Duration::from_secs),
+            ..HookRunner::default()
+        }
+    }
+}
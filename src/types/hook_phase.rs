@@ -0,0 +1,25 @@
+use clap::ValueEnum;
+use strum::Display;
+
+/// A point in a repoconf command where the hooks from `<hooks-dir>/<phase>` and `<hooks-dir>/<phase>.d/` are run (see [`HookRunner`](crate::HookRunner))
+#[derive(ValueEnum, Display, Ord, PartialOrd, Eq, PartialEq, Hash, Clone, Copy, Debug)]
+#[value(rename_all = "kebab-case")]
+#[strum(serialize_all = "kebab-case")]
+pub enum HookPhase {
+    /// Before `init` checks out the template
+    PreInit,
+    /// After `init` has pushed the new repository
+    PostInit,
+    /// Before `merge` merges the template remotes
+    PreMerge,
+    /// After a template remote has been merged, before the merge commit is created
+    PreMergeCommit,
+    /// After all template remotes have been merged and committed
+    PostMerge,
+    /// Before the merged branch is pushed
+    PrePush,
+    /// After `propagate` has merged all repositories
+    PostPropagate,
+}
+
+impl HookPhase {}
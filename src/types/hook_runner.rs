@@ -0,0 +1,484 @@
+use crate::{HookPhase, HookTrustStore, HookTrustStoreIsTrustedError, HookTrustStoreNewError, HookTrustStoreTrustHookError, TaskError, task};
+use demand::{DemandOption, Select};
+use errgonomic::{ErrVec, handle, handle_bool, handle_iter};
+use itertools::Itertools;
+use std::fs::{metadata, read, read_dir};
+use std::io;
+use std::io::Read;
+use std::iter::once;
+use std::os::unix::fs::PermissionsExt;
+use std::os::unix::process::CommandExt;
+use std::path::{Path, PathBuf};
+use std::process::{Child, ExitStatus, Stdio};
+use std::str::from_utf8;
+use std::thread::{JoinHandle, sleep, spawn};
+use std::time::{Duration, Instant};
+use thiserror::Error;
+use xshell::{Cmd, Shell, cmd};
+
+/// Runs the hooks of a repository
+///
+/// The hooks of a phase are discovered in a hooks directory: `<hooks-dir>/<phase>` (or `<hooks-dir>/<phase>.sh`), then every file in `<hooks-dir>/<phase>.d/` in the order of file names. Every hook is called with the repository directory as the first argument.
+///
+/// The template-owned hooks live in `.repoconf/hooks` and are run first: either from the current directory of the shell or, if [`Self::template_hooks_dirs`] is set, from the template revisions. The child-owned hooks live in `.repoconf/local/hooks` and are always run from the current directory of the shell.
+///
+/// Every hook receives the following environment variables (the variables that don't apply to the command or the phase are not set):
+///
+/// * `REPOCONF_HOOK_PHASE`: the phase of the hook (e.g. `post-merge`)
+/// * `REPOCONF_DIR`: the repository directory
+/// * `REPOCONF_COMMAND`: the repoconf command that runs the hook (e.g. `merge`)
+/// * `REPOCONF_NO_PUSH`: `1` if the command commits its changes without pushing them, `0` otherwise
+/// * `REPOCONF_REMOTE`: the template remote (e.g. `repoconf-rust-lib`)
+/// * `REPOCONF_REMOTES`: the space-separated template remotes of a command that merges several templates
+/// * `REPOCONF_TEMPLATE_NAME`: the template name (e.g. `rust-lib`)
+/// * `REPOCONF_TEMPLATE_URL`: the template repo URL (also available as `REPOCONF_TEMPLATE`)
+/// * `REPOCONF_LOCAL_BRANCH`: the local branch that receives the template changes
+/// * `REPOCONF_REMOTE_BRANCH`: the template branch that is merged
+/// * `REPOCONF_MERGED_RANGE`: the commit range that is merged (`<base>..<tip>`, suitable for `git log`)
+/// * `REPOCONF_VAR_<NAME>`: the value of a template variable (`init` and `create` only, see [`crate::TemplateVariable`])
+///
+/// `create` additionally passes `REPOCONF_VISIBILITY`, `REPOCONF_REPO_OWNER` and `REPOCONF_REPO_NAME` to the `init` hooks.
+///
+/// The template-owned hooks are arbitrary code from a third-party repository, so they are run only if they are trusted for the template that owns them (see [`HookTrustStore`]). A new or changed hook requires an approval, which is requested interactively or refused in the non-interactive mode. The approval is recorded only for the owner: the template of the extracted hooks directory, or the single template of [`Self::template_urls`] (the user selects the owner if the hooks of several templates are run from the current directory of the shell).
+#[derive(Default, Clone, Debug)]
+pub struct HookRunner {
+    /// Environment variables passed to every hook (a later variable overrides an earlier variable with the same name)
+    pub env: Vec<(String, String)>,
+    /// Directories with the template-owned hooks extracted from the template revisions, paired with the URLs of the templates that own them (see [`Self::with_template_hooks`]); the template-owned hooks are run from the current directory of the shell if `None`
+    pub template_hooks_dirs: Option<Vec<(PathBuf, String)>>,
+    /// Run the hooks with a closed stdin and `CI=1`, and capture their output instead of passing it through
+    pub non_interactive: bool,
+    /// Maximum duration of a single hook (the hook is killed after it)
+    pub timeout: Option<Duration>,
+    /// URLs of the templates that provide the template-owned hooks (the template-owned hooks are not checked against the trust store if empty)
+    pub template_urls: Vec<String>,
+}
+
+impl HookRunner {
+    pub const HOOKS_DIR: &'static str = ".repoconf/hooks";
+
+    pub const LOCAL_HOOKS_DIR: &'static str = ".repoconf/local/hooks";
+
+    /// How often a hook with a timeout is checked for completion
+    const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+    pub fn with_env(self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        Self {
+            env: self
+                .env
+                .into_iter()
+                .chain(once((name.into(), value.into())))
+                .collect(),
+            ..self
+        }
+    }
+
+    /// Extracts the template-owned hooks of the template `remote` at `revision` to a temporary directory and switches the runner to the extracted hooks
+    ///
+    /// The hooks of several templates are run in the order of the calls. A template without hooks contributes no hooks.
+    pub fn with_template_hooks(self, sh: &Shell, remote: &str, revision: &str) -> Result<Self, HookRunnerWithTemplateHooksError> {
+        use HookRunnerWithTemplateHooksError::*;
+        let hooks_dir = Self::HOOKS_DIR;
+        let hooks_tree = handle!(cmd!(sh, "git ls-tree --full-tree -d --name-only {revision} -- {hooks_dir}").read(), GitLsTreeFailed, revision);
+        let extracted_hooks_dir = if hooks_tree.is_empty() {
+            None
+        } else {
+            let template_url = handle!(cmd!(sh, "git remote get-url {remote}").read(), GitRemoteGetUrlFailed, remote);
+            let extract_dir = PathBuf::from(handle!(cmd!(sh, "git rev-parse --path-format=absolute --git-path repoconf/template-hooks/{remote}").read(), GitExtractDirReadFailed, remote));
+            let archive = extract_dir.with_file_name(format!("{remote}.tar"));
+            handle!(sh.remove_path(&extract_dir), RemovePathFailed, path: extract_dir);
+            handle!(sh.create_dir(&extract_dir), CreateDirFailed, path: extract_dir);
+            handle!(cmd!(sh, "git archive --format=tar --output={archive} {revision} {hooks_dir}").run_echo(), GitArchiveFailed, revision);
+            handle!(cmd!(sh, "tar -xf {archive} -C {extract_dir}").run_echo(), TarExtractFailed, archive);
+            handle!(sh.remove_path(&archive), RemovePathFailed, path: archive);
+            Some((extract_dir.join(hooks_dir), template_url))
+        };
+        Ok(Self {
+            template_hooks_dirs: Some(
+                self.template_hooks_dirs
+                    .unwrap_or_default()
+                    .into_iter()
+                    .chain(extracted_hooks_dir)
+                    .collect(),
+            ),
+            ..self
+        })
+    }
+
+    /// Adds the environment variables that describe the template `remote`
+    pub fn with_template(self, sh: &Shell, remote: &str) -> Result<Self, HookRunnerWithTemplateError> {
+        use HookRunnerWithTemplateError::*;
+        let template_url = handle!(cmd!(sh, "git remote get-url {remote}").read(), GitRemoteGetUrlFailed, remote);
+        let template_name = remote.strip_prefix("repoconf-").unwrap_or(remote);
+        Ok(self
+            .with_env("REPOCONF_REMOTE", remote)
+            .with_env("REPOCONF_TEMPLATE_NAME", template_name)
+            .with_env("REPOCONF_TEMPLATE_URL", &template_url)
+            .with_env("REPOCONF_TEMPLATE", &template_url)
+            .with_template_url(template_url))
+    }
+
+    /// Adds the URLs of the template `remotes` as the owners of the template-owned hooks (without describing a single template in the environment)
+    pub fn with_template_urls(self, sh: &Shell, remotes: &[String]) -> Result<Self, HookRunnerWithTemplateUrlsError> {
+        use HookRunnerWithTemplateUrlsError::*;
+        let template_urls = handle_iter!(
+            remotes
+                .iter()
+                .map(|remote| cmd!(sh, "git remote get-url {remote}").read()),
+            GitRemoteGetUrlsFailed
+        );
+        Ok(template_urls
+            .into_iter()
+            .fold(self, Self::with_template_url))
+    }
+
+    pub fn with_template_url(self, url: impl Into<String>) -> Self {
+        Self {
+            template_urls: self
+                .template_urls
+                .into_iter()
+                .chain(once(url.into()))
+                .collect(),
+            ..self
+        }
+    }
+
+    /// Returns the hooks of the `phase` in the order they should be run: the template-owned hooks, then the child-owned hooks of the repository in `dir`
+    pub fn hooks(&self, dir: &Path, phase: HookPhase) -> Result<Vec<PathBuf>, HookRunnerHooksError> {
+        use HookRunnerHooksError::*;
+        let hooks_dirs = self
+            .template_hooks_dirs(dir)
+            .into_iter()
+            .map(|(hooks_dir, _)| hooks_dir)
+            .chain(once(dir.join(Self::LOCAL_HOOKS_DIR)));
+        let hooks = handle_iter!(hooks_dirs.map(|hooks_dir| Self::phase_hooks(&hooks_dir, phase)), PhaseHooksFailed, phase);
+        Ok(hooks.into_iter().flatten().collect())
+    }
+
+    /// Returns the directories with the template-owned hooks for the repository in `dir`, paired with the URLs of the templates that own them (`None` if the owner is unknown)
+    fn template_hooks_dirs(&self, dir: &Path) -> Vec<(PathBuf, Option<String>)> {
+        match &self.template_hooks_dirs {
+            Some(template_hooks_dirs) => template_hooks_dirs
+                .iter()
+                .map(|(hooks_dir, url)| (hooks_dir.clone(), Some(url.clone())))
+                .collect(),
+            None => vec![(dir.join(Self::HOOKS_DIR), None)],
+        }
+    }
+
+    /// Returns the hooks of the `phase` in `hooks_dir`
+    fn phase_hooks(hooks_dir: &Path, phase: HookPhase) -> Result<Vec<PathBuf>, HookRunnerPhaseHooksError> {
+        use HookRunnerPhaseHooksError::*;
+        let main_hooks = [
+            hooks_dir.join(phase.to_string()),
+            hooks_dir.join(format!("{phase}.sh")),
+        ]
+        .into_iter()
+        .filter(|path| path.is_file());
+        let hooks_d_dir = hooks_dir.join(format!("{phase}.d"));
+        let hooks_d = if hooks_d_dir.is_dir() {
+            let entries = handle!(read_dir(&hooks_d_dir), ReadDirFailed, dir: hooks_d_dir);
+            let entries = handle_iter!(entries, ReadDirEntryFailed, dir: hooks_d_dir);
+            entries
+                .into_iter()
+                .map(|entry| entry.path())
+                .filter(|path| path.is_file())
+                .sorted()
+                .collect_vec()
+        } else {
+            vec![]
+        };
+        Ok(main_hooks.chain(hooks_d).collect())
+    }
+
+    /// Runs all hooks of the `phase` in the current directory of `sh`
+    pub fn run(&self, sh: &Shell, phase: HookPhase) -> Result<(), HookRunnerRunError> {
+        use HookRunnerRunError::*;
+        let dir = sh.current_dir();
+        let hooks = handle!(self.hooks(&dir, phase), HooksFailed, phase);
+        let template_hooks_dirs = self.template_hooks_dirs(&dir);
+        hooks.iter().try_for_each(|path| {
+            // The hooks of the current directory of the shell are not checked against the trust store if the command doesn't know its templates
+            let owner_url = template_hooks_dirs
+                .iter()
+                .find(|(template_hooks_dir, _)| path.starts_with(template_hooks_dir))
+                .map(|(_, owner_url)| owner_url.as_deref())
+                .filter(|owner_url| owner_url.is_some() || !self.template_urls.is_empty());
+            if let Some(owner_url) = owner_url {
+                handle!(self.ensure_trusted(sh, phase, path, owner_url), EnsureTrustedFailed, phase, path);
+            }
+            handle!(self.run_hook(sh, phase, path), RunHookFailed, phase, path);
+            Ok(())
+        })
+    }
+
+    /// Checks that the template-owned hook at `path` is trusted and asks the user to approve it otherwise
+    ///
+    /// The approval is recorded only for the template that owns the hook: the `owner_url` if it is known, otherwise the single template of [`Self::template_urls`] or the template selected by the user.
+    fn ensure_trusted(&self, sh: &Shell, phase: HookPhase, path: &Path, owner_url: Option<&str>) -> Result<(), HookRunnerEnsureTrustedError> {
+        use HookRunnerEnsureTrustedError::*;
+        let candidate_urls = match owner_url {
+            Some(owner_url) => vec![owner_url.to_string()],
+            None => self.template_urls.clone(),
+        };
+        let hash = handle!(cmd!(sh, "git hash-object {path}").read(), GitHashObjectFailed);
+        let store = handle!(HookTrustStore::new(), HookTrustStoreNewFailed);
+        let trusted = handle_iter!(
+            candidate_urls
+                .iter()
+                .map(|url| store.is_trusted(sh, url, &hash)),
+            IsTrustedFailed
+        );
+        if trusted.contains(&true) {
+            return Ok(());
+        }
+        let urls = candidate_urls.join(", ");
+        handle_bool!(self.non_interactive, HookNotTrusted, path, hash, urls, store_path: store.path);
+        let title = format!("Run the new or changed {phase} hook '{path}' from {urls}?", path = path.display());
+        let confirmed = handle!(task(title), TaskFailed);
+        handle_bool!(!confirmed, HookRejected, path);
+        let url = match candidate_urls.as_slice() {
+            [url] => url.clone(),
+            _ => {
+                // The hooks in the current directory of the shell may come from any of the merged templates, so only the user knows which template should be trusted
+                let select = Select::new("Template that provides the hook").options(candidate_urls.into_iter().map(DemandOption::new).collect());
+                handle!(select.run(), SelectRunFailed)
+            }
+        };
+        handle!(store.trust_hook(sh, &url, &hash), TrustHookFailed, url);
+        Ok(())
+    }
+
+    /// Runs a single hook in the current directory of `sh`
+    ///
+    /// The executable scripts with a shebang and the executable binaries are executed directly, so a hook may be written in any language (a hook that needs `usage` for argument parsing can declare `#!/usr/bin/env -S usage bash`). The non-executable scripts with a shebang are run with the interpreter from the shebang, the scripts without a shebang are run with `bash`. The non-executable binaries are skipped, because the file modes of the repository are never changed.
+    pub fn run_hook(&self, sh: &Shell, phase: HookPhase, path: &Path) -> Result<(), HookRunnerRunHookError> {
+        use HookRunnerRunHookError::*;
+        let dir = sh.current_dir();
+        let is_executable = handle!(metadata(path), MetadataFailed).permissions().mode() & 0o111 != 0;
+        let contents = handle!(read(path), ReadFailed);
+        let shebang = contents.strip_prefix(b"#!").map(|rest| {
+            String::from_utf8_lossy(rest.split(|byte| *byte == b'\n').next().unwrap_or_default())
+                .trim()
+                .to_string()
+        });
+        let hook_cmd = match shebang {
+            Some(_) if is_executable => cmd!(sh, "{path} {dir}"),
+            Some(shebang) => {
+                // Like the kernel, pass everything after the interpreter as a single argument
+                let (interpreter, argument) = match shebang.split_once(char::is_whitespace) {
+                    Some((interpreter, argument)) => (interpreter.to_string(), Some(argument.trim().to_string())),
+                    None => (shebang, None),
+                };
+                cmd!(sh, "{interpreter} {argument...} {path} {dir}")
+            }
+            None if from_utf8(&contents).is_ok() => cmd!(sh, "bash {path} {dir}"),
+            None if is_executable => cmd!(sh, "{path} {dir}"),
+            None => {
+                eprintln!("[SKIP] {phase} {path} is neither executable nor a script", path = path.display());
+                return Ok(());
+            }
+        };
+        eprintln!("[HOOK] {phase} {path}", path = path.display());
+        let hook_cmd = hook_cmd
+            .env("REPOCONF_HOOK_PHASE", phase.to_string())
+            .env("REPOCONF_DIR", &dir);
+        handle!(self.run_command(sh, hook_cmd), RunCommandFailed);
+        Ok(())
+    }
+
+    /// Runs the `cmd` with the environment variables, the stdin and the timeout of the hooks (see [`Self::env`], [`Self::non_interactive`] and [`Self::timeout`])
+    ///
+    /// In the non-interactive mode, the output of the command is captured: it is printed after the command succeeds and attached to the error if the command fails or times out.
+    pub fn run_command(&self, sh: &Shell, cmd: Cmd<'_>) -> Result<(), HookRunnerRunCommandError> {
+        use HookRunnerRunCommandError::*;
+        let mut command = cmd
+            .envs(self.env.iter().map(|(name, value)| (name, value)))
+            .to_command();
+        if self.non_interactive {
+            command
+                .env("CI", "1")
+                .stdin(Stdio::null())
+                .stdout(Stdio::piped())
+                .stderr(Stdio::piped())
+                // A separate process group allows to kill the processes spawned by the command, which would keep the pipes open otherwise (an interactive command stays in the foreground process group to read from the terminal)
+                .process_group(0);
+        }
+        let mut child = handle!(command.spawn(), SpawnFailed);
+        let stdout_reader = child.stdout.take().map(Self::read_pipe);
+        let stderr_reader = child.stderr.take().map(Self::read_pipe);
+        let status = match self.timeout {
+            Some(timeout) => match handle!(Self::wait_timeout(&mut child, timeout), WaitTimeoutFailed) {
+                Some(status) => status,
+                None => {
+                    // The command must be killed before joining the readers, because the pipes are closed only when the command and its children exit
+                    if self.non_interactive {
+                        let process_group = format!("-{pid}", pid = child.id());
+                        handle!(cmd!(sh, "kill -KILL -- {process_group}").run(), KillProcessGroupFailed, process_group);
+                    } else {
+                        handle!(child.kill(), KillFailed);
+                    }
+                    handle!(child.wait(), WaitFailed);
+                    return Err(CommandTimedOut {
+                        timeout,
+                        stdout: Self::join_pipe(stdout_reader),
+                        stderr: Self::join_pipe(stderr_reader),
+                    });
+                }
+            },
+            None => handle!(child.wait(), WaitFailed),
+        };
+        let stdout = Self::join_pipe(stdout_reader);
+        let stderr = Self::join_pipe(stderr_reader);
+        handle_bool!(!status.success(), CommandFailed, status, stdout, stderr);
+        print!("{stdout}");
+        eprint!("{stderr}");
+        Ok(())
+    }
+
+    /// Waits for the `child` to exit and returns `None` if it is still running after the `timeout`
+    fn wait_timeout(child: &mut Child, timeout: Duration) -> Result<Option<ExitStatus>, HookRunnerWaitTimeoutError> {
+        use HookRunnerWaitTimeoutError::*;
+        let started = Instant::now();
+        loop {
+            match handle!(child.try_wait(), TryWaitFailed) {
+                Some(status) => return Ok(Some(status)),
+                None if started.elapsed() >= timeout => return Ok(None),
+                None => sleep(Self::POLL_INTERVAL),
+            }
+        }
+    }
+
+    /// Reads the `pipe` in a separate thread, so that a hook with a large output doesn't block on a full pipe
+    fn read_pipe(mut pipe: impl Read + Send + 'static) -> JoinHandle<String> {
+        spawn(move || {
+            let mut output = Vec::new();
+            // A read error truncates the output, which is only used for diagnostics
+            pipe.read_to_end(&mut output).ok();
+            String::from_utf8_lossy(&output).into_owned()
+        })
+    }
+
+    fn join_pipe(reader: Option<JoinHandle<String>>) -> String {
+        reader
+            .and_then(|reader| reader.join().ok())
+            .unwrap_or_default()
+    }
+
+    /// Formats the captured output of a hook for an error message (empty if the output wasn't captured)
+    pub fn format_captured_output(stdout: &str, stderr: &str) -> String {
+        [("stdout", stdout), ("stderr", stderr)]
+            .into_iter()
+            .filter(|(_, output)| !output.trim().is_empty())
+            .map(|(name, output)| format!("\n--- {name} ---\n{output}", output = output.trim_end()))
+            .collect()
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum HookRunnerWithTemplateError {
+    #[error("failed to read the URL of template remote '{remote}'")]
+    GitRemoteGetUrlFailed { source: xshell::Error, remote: String },
+}
+
+#[derive(Error, Debug)]
+pub enum HookRunnerWithTemplateUrlsError {
+    #[error("failed to read the URLs of {len} template remotes", len = source.len())]
+    GitRemoteGetUrlsFailed { source: ErrVec<xshell::Error> },
+}
+
+#[derive(Error, Debug)]
+pub enum HookRunnerWithTemplateHooksError {
+    #[error("failed to list the hooks of revision '{revision}'")]
+    GitLsTreeFailed { source: xshell::Error, revision: String },
+    #[error("failed to read the URL of template remote '{remote}'")]
+    GitRemoteGetUrlFailed { source: xshell::Error, remote: String },
+    #[error("failed to resolve the hooks directory of template remote '{remote}'")]
+    GitExtractDirReadFailed { source: xshell::Error, remote: String },
+    #[error("failed to remove '{path}'")]
+    RemovePathFailed { source: xshell::Error, path: PathBuf },
+    #[error("failed to create directory '{path}'")]
+    CreateDirFailed { source: xshell::Error, path: PathBuf },
+    #[error("failed to archive the hooks of revision '{revision}'")]
+    GitArchiveFailed { source: xshell::Error, revision: String },
+    #[error("failed to extract the hooks archive '{archive}'")]
+    TarExtractFailed { source: xshell::Error, archive: PathBuf },
+}
+
+#[derive(Error, Debug)]
+pub enum HookRunnerHooksError {
+    #[error("failed to discover the {phase} hooks in {len} hooks directories", len = source.len())]
+    PhaseHooksFailed { source: ErrVec<HookRunnerPhaseHooksError>, phase: HookPhase },
+}
+
+#[derive(Error, Debug)]
+pub enum HookRunnerPhaseHooksError {
+    #[error("failed to read hooks directory '{dir}'")]
+    ReadDirFailed { source: io::Error, dir: PathBuf },
+    #[error("failed to read {len} entries of hooks directory '{dir}'", len = source.len())]
+    ReadDirEntryFailed { source: ErrVec<io::Error>, dir: PathBuf },
+}
+
+#[derive(Error, Debug)]
+pub enum HookRunnerRunError {
+    #[error("failed to discover the {phase} hooks")]
+    HooksFailed { source: HookRunnerHooksError, phase: HookPhase },
+    #[error("failed to check the trust of {phase} hook '{path}'")]
+    EnsureTrustedFailed { source: HookRunnerEnsureTrustedError, phase: HookPhase, path: PathBuf },
+    #[error("{phase} hook '{path}' failed")]
+    RunHookFailed { source: HookRunnerRunHookError, phase: HookPhase, path: PathBuf },
+}
+
+#[derive(Error, Debug)]
+pub enum HookRunnerEnsureTrustedError {
+    #[error("failed to hash the hook")]
+    GitHashObjectFailed { source: xshell::Error },
+    #[error("failed to locate the hook trust store")]
+    HookTrustStoreNewFailed { source: HookTrustStoreNewError },
+    #[error("failed to check the trust of {len} templates", len = source.len())]
+    IsTrustedFailed { source: ErrVec<HookTrustStoreIsTrustedError> },
+    #[error("hook '{path}' (hash {hash}) from {urls} is not trusted; run the command interactively to approve it or trust the template with `git config --file {store_path} template.<url>.trusted true`")]
+    HookNotTrusted { path: PathBuf, hash: String, urls: String, store_path: PathBuf },
+    #[error("failed to ask for the approval of the hook")]
+    TaskFailed { source: TaskError },
+    #[error("hook '{path}' has been rejected")]
+    HookRejected { path: PathBuf },
+    #[error("failed to prompt for the template that provides the hook")]
+    SelectRunFailed { source: io::Error },
+    #[error("failed to record the approval of the hook for '{url}'")]
+    TrustHookFailed { source: HookTrustStoreTrustHookError, url: String },
+}
+
+#[derive(Error, Debug)]
+pub enum HookRunnerRunHookError {
+    #[error("failed to read the metadata of the hook")]
+    MetadataFailed { source: io::Error },
+    #[error("failed to read the hook")]
+    ReadFailed { source: io::Error },
+    #[error("failed to run the hook")]
+    RunCommandFailed { source: HookRunnerRunCommandError },
+}
+
+#[derive(Error, Debug)]
+pub enum HookRunnerRunCommandError {
+    #[error("failed to start the command")]
+    SpawnFailed { source: io::Error },
+    #[error("failed to wait for the command with a timeout")]
+    WaitTimeoutFailed { source: HookRunnerWaitTimeoutError },
+    #[error("failed to wait for the command")]
+    WaitFailed { source: io::Error },
+    #[error("failed to kill the command after the timeout")]
+    KillFailed { source: io::Error },
+    #[error("failed to kill the process group '{process_group}' of the command after the timeout")]
+    KillProcessGroupFailed { source: xshell::Error, process_group: String },
+    #[error("command timed out after {timeout:?}{}", HookRunner::format_captured_output(.stdout, .stderr))]
+    CommandTimedOut { timeout: Duration, stdout: String, stderr: String },
+    #[error("command exited with {status}{}", HookRunner::format_captured_output(.stdout, .stderr))]
+    CommandFailed { status: ExitStatus, stdout: String, stderr: String },
+}
+
+#[derive(Error, Debug)]
+pub enum HookRunnerWaitTimeoutError {
+    #[error("failed to check whether the command has exited")]
+    TryWaitFailed { source: io::Error },
+}
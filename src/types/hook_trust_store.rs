@@ -0,0 +1,101 @@
+use errgonomic::{handle, handle_bool, handle_opt};
+use std::env::var_os;
+use std::io;
+use std::path::PathBuf;
+use std::process::Output;
+use thiserror::Error;
+use xshell::{Shell, cmd};
+
+/// Hooks approved by the user, stored in a git config file outside of any repository (`$XDG_CONFIG_HOME/repoconf/trust` or `~/.config/repoconf/trust`)
+///
+/// A template is trusted as a whole with `template.<url>.trusted = true`. A single hook is trusted with a `template.<url>.hook = <hash>` entry, where the hash is the git blob hash of the hook contents, so a changed hook requires a new approval.
+#[derive(Eq, PartialEq, Hash, Clone, Debug)]
+pub struct HookTrustStore {
+    pub path: PathBuf,
+}
+
+impl HookTrustStore {
+    pub fn new() -> Result<Self, HookTrustStoreNewError> {
+        use HookTrustStoreNewError::*;
+        let config_dir = match var_os("XDG_CONFIG_HOME").filter(|dir| !dir.is_empty()) {
+            Some(config_dir) => PathBuf::from(config_dir),
+            None => PathBuf::from(handle_opt!(var_os("HOME"), HomeDirNotFound)).join(".config"),
+        };
+        Ok(Self {
+            path: config_dir.join("repoconf").join("trust"),
+        })
+    }
+
+    /// Returns true if the template with `url` is trusted as a whole or the hook with `hash` has been approved for it
+    pub fn is_trusted(&self, sh: &Shell, url: &str, hash: &str) -> Result<bool, HookTrustStoreIsTrustedError> {
+        use HookTrustStoreIsTrustedError::*;
+        let trusted_key = format!("template.{url}.trusted");
+        let trusted = handle!(self.get_all(sh, &trusted_key), GetAllFailed, key: trusted_key);
+        if trusted.last().map(String::as_str) == Some("true") {
+            return Ok(true);
+        }
+        let hook_key = format!("template.{url}.hook");
+        let hashes = handle!(self.get_all(sh, &hook_key), GetAllFailed, key: hook_key);
+        Ok(hashes.iter().any(|trusted_hash| trusted_hash == hash))
+    }
+
+    /// Records the approval of the hook with `hash` for the template with `url`
+    pub fn trust_hook(&self, sh: &Shell, url: &str, hash: &str) -> Result<(), HookTrustStoreTrustHookError> {
+        use HookTrustStoreTrustHookError::*;
+        let path = &self.path;
+        if let Some(parent) = path.parent() {
+            handle!(sh.create_dir(parent), CreateDirFailed, dir: parent);
+        }
+        let hook_key = format!("template.{url}.hook");
+        handle!(cmd!(sh, "git config --file {path} --add {hook_key} {hash}").run_echo(), GitConfigAddFailed, key: hook_key);
+        Ok(())
+    }
+
+    fn get_all(&self, sh: &Shell, key: &str) -> Result<Vec<String>, HookTrustStoreGetAllError> {
+        use HookTrustStoreGetAllError::*;
+        let path = &self.path;
+        let output = handle!(
+            cmd!(sh, "git config --file {path} --get-all {key}")
+                .to_command()
+                .output(),
+            OutputFailed,
+            key
+        );
+        // `git config --get-all` exits with 1 if the key is not set (including the case when the file doesn't exist)
+        let is_expected = output.status.success() || output.status.code() == Some(1);
+        handle_bool!(!is_expected, UnexpectedOutput, key, output);
+        let values = String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .map(ToOwned::to_owned)
+            .collect();
+        Ok(values)
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum HookTrustStoreNewError {
+    #[error("failed to locate the home directory (neither XDG_CONFIG_HOME nor HOME is set)")]
+    HomeDirNotFound,
+}
+
+#[derive(Error, Debug)]
+pub enum HookTrustStoreIsTrustedError {
+    #[error("failed to read trust store key '{key}'")]
+    GetAllFailed { source: HookTrustStoreGetAllError, key: String },
+}
+
+#[derive(Error, Debug)]
+pub enum HookTrustStoreTrustHookError {
+    #[error("failed to create directory '{dir}'")]
+    CreateDirFailed { source: xshell::Error, dir: PathBuf },
+    #[error("failed to add trust store key '{key}'")]
+    GitConfigAddFailed { source: xshell::Error, key: String },
+}
+
+#[derive(Error, Debug)]
+pub enum HookTrustStoreGetAllError {
+    #[error("failed to read trust store key '{key}'")]
+    OutputFailed { source: io::Error, key: String },
+    #[error("unexpected output while reading trust store key '{key}'")]
+    UnexpectedOutput { key: String, output: Output },
+}
@@ -0,0 +1,64 @@
+/// An entry of `git ls-files --stage` output
+#[derive(Eq, PartialEq, Hash, Clone, Debug)]
+pub struct IndexEntry {
+    pub mode: String,
+    pub hash: String,
+    pub stage: String,
+    pub path: String,
+}
+
+impl IndexEntry {
+    /// Parses a NUL-terminated record of `git ls-files --stage -z` output (`<mode> <hash> <stage>\t<path>`)
+    pub fn parse(record: &str) -> Option<Self> {
+        let (meta, path) = record.split_once('\t')?;
+        let mut meta_parts = meta.split(' ');
+        let mode = meta_parts.next()?.to_string();
+        let hash = meta_parts.next()?.to_string();
+        let stage = meta_parts.next()?.to_string();
+        Some(Self {
+            mode,
+            hash,
+            stage,
+            path: path.to_string(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn must_parse_a_record() {
+        let entry = IndexEntry::parse("100644 0123456789abcdef0123456789abcdef01234567 0\tdocs/a file.md").unwrap();
+        assert_eq!(
+            entry,
+            IndexEntry {
+                mode: "100644".to_string(),
+                hash: "0123456789abcdef0123456789abcdef01234567".to_string(),
+                stage: "0".to_string(),
+                path: "docs/a file.md".to_string(),
+            }
+        );
+    }
+
+    #[test]
+    fn must_reject_a_record_without_path() {
+        assert_eq!(IndexEntry::parse("100644 0123456789abcdef0123456789abcdef01234567 0"), None);
+    }
+
+    #[test]
+    fn must_reject_a_record_without_stage() {
+        assert_eq!(IndexEntry::parse("100644 0123456789abcdef0123456789abcdef01234567\tREADME.md"), None);
+    }
+
+    #[test]
+    fn must_reject_a_record_without_hash() {
+        assert_eq!(IndexEntry::parse("100644\tREADME.md"), None);
+    }
+
+    #[test]
+    fn must_reject_an_empty_record() {
+        assert_eq!(IndexEntry::parse(""), None);
+    }
+}
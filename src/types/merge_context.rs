@@ -0,0 +1,15 @@
+use crate::{BranchNameStrategy, HookRunner, Provisioner};
+
+/// The settings of a template merge that are shared by the template remotes of a single run
+#[derive(Clone, Copy, Debug)]
+pub struct MergeContext<'a> {
+    /// Runs the hooks of the merge (extended with the environment of the merged template for every template remote)
+    pub hook_runner: &'a HookRunner,
+    pub provisioner: Provisioner,
+    /// Resolves the remote branch of the templates that don't set [`TemplateConfig::branch`](crate::TemplateConfig::branch)
+    pub remote_branch_strategy: &'a BranchNameStrategy,
+    pub refs: &'a [String],
+    pub allow_unrelated_histories: bool,
+    /// Template remotes whose changes earlier in the same run are overridden by the merged template (see [`MergeCommand::order_remotes`](crate::MergeCommand::order_remotes))
+    pub overridable_remotes: &'a [String],
+}
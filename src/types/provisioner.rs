@@ -0,0 +1,127 @@
+use crate::{GitConfigGetAll, GitConfigGetAllError};
+use clap::ValueEnum;
+use errgonomic::{handle, handle_bool, handle_opt};
+use serde_json::Value;
+use std::path::{Path, PathBuf};
+use strum::Display;
+use thiserror::Error;
+use xshell::{Shell, cmd};
+
+/// Installs the toolchain of the child repository before the merge is committed, so that the pre-commit hooks run with the tools that the merged configs require
+///
+/// Configured per repository with `git config repoconf.provisioner <provisioner>`.
+#[derive(ValueEnum, Display, Ord, PartialOrd, Eq, PartialEq, Default, Hash, Clone, Copy, Debug)]
+#[value(rename_all = "kebab-case")]
+#[strum(serialize_all = "kebab-case")]
+pub enum Provisioner {
+    /// Run `mise install` if the repository has a mise config
+    #[default]
+    Mise,
+    /// Run `asdf install` if the repository has a `.tool-versions` file
+    Asdf,
+    /// Run `rustup toolchain install` if the repository has a `rust-toolchain.toml` (or `rust-toolchain`) file
+    Rustup,
+    /// Run `nix develop --command true` if the repository has a `flake.nix` file
+    Nix,
+    /// Don't install anything
+    None,
+}
+
+impl Provisioner {
+    pub const CONFIG_KEY: &'static str = "repoconf.provisioner";
+
+    /// The files that mise reads the tools from (relative to the repository root)
+    pub const MISE_CONFIG_FILES: &'static [&'static str] = &[
+        "mise.toml",
+        ".mise.toml",
+        "mise.local.toml",
+        ".mise.local.toml",
+        "mise/config.toml",
+        ".mise/config.toml",
+        ".config/mise.toml",
+        ".config/mise/config.toml",
+        ".tool-versions",
+    ];
+
+    pub fn load(sh_dir: &Shell) -> Result<Self, ProvisionerLoadError> {
+        use ProvisionerLoadError::*;
+        let key = Self::CONFIG_KEY;
+        let provisioner = handle!(sh_dir.git_config_get_all(key), GitConfigGetAllFailed, key).pop();
+        match provisioner {
+            Some(provisioner) => Ok(handle_opt!(Self::from_str(&provisioner, true).ok(), ProvisionerInvalid, provisioner)),
+            None => Ok(Self::default()),
+        }
+    }
+
+    /// Installs the toolchain of the repository in the current directory of `sh_dir` (does nothing if the repository doesn't configure a toolchain for this provisioner)
+    pub fn provision(self, sh_dir: &Shell) -> Result<(), ProvisionerProvisionError> {
+        use ProvisionerProvisionError::*;
+        if self == Self::None {
+            return Ok(());
+        }
+        let repository_root = PathBuf::from(handle!(cmd!(sh_dir, "git rev-parse --path-format=absolute --show-toplevel").read(), GitRepositoryRootReadFailed));
+        let has_file = |names: &[&str]| {
+            names
+                .iter()
+                .any(|name| sh_dir.path_exists(repository_root.join(name)))
+        };
+        match self {
+            // The mise config is detected before running mise, so that a repository without a mise config doesn't require mise to be installed
+            Self::Mise if has_file(Self::MISE_CONFIG_FILES) => handle!(Self::provision_mise(sh_dir, &repository_root), ProvisionMiseFailed),
+            Self::Asdf if has_file(&[".tool-versions"]) => handle!(cmd!(sh_dir, "asdf install").run_interactive(), InstallFailed, provisioner: self),
+            Self::Rustup if has_file(&["rust-toolchain.toml", "rust-toolchain"]) => handle!(cmd!(sh_dir, "rustup toolchain install").run_interactive(), InstallFailed, provisioner: self),
+            Self::Nix if has_file(&["flake.nix"]) => handle!(cmd!(sh_dir, "nix develop --command true").run_interactive(), InstallFailed, provisioner: self),
+            Self::Mise | Self::Asdf | Self::Rustup | Self::Nix | Self::None => {}
+        }
+        Ok(())
+    }
+
+    fn provision_mise(sh_dir: &Shell, repository_root: &Path) -> Result<(), ProvisionerProvisionMiseError> {
+        use ProvisionerProvisionMiseError::*;
+        let mise_configs_json = handle!(cmd!(sh_dir, "mise --no-hooks config ls --json").read(), MiseConfigListFailed);
+        let mise_configs = handle!(serde_json::from_str::<Vec<Value>>(&mise_configs_json), FromStrFailed, json: mise_configs_json);
+        handle_bool!(
+            mise_configs.iter().any(|config| config.get("path").and_then(Value::as_str).is_none()),
+            MiseConfigListInvalid,
+            configs: mise_configs
+        );
+        let has_repository_mise_config = mise_configs
+            .iter()
+            .filter_map(|config| config.get("path").and_then(Value::as_str))
+            .any(|path| Path::new(path).starts_with(repository_root));
+        if has_repository_mise_config {
+            handle!(cmd!(sh_dir, "mise install").run_interactive(), MiseInstallFailed);
+        }
+        Ok(())
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum ProvisionerLoadError {
+    #[error("failed to read git config key '{key}'")]
+    GitConfigGetAllFailed { source: GitConfigGetAllError, key: String },
+    #[error("git config key 'repoconf.provisioner' has an invalid value '{provisioner}'")]
+    ProvisionerInvalid { provisioner: String },
+}
+
+#[derive(Error, Debug)]
+pub enum ProvisionerProvisionError {
+    #[error("failed to resolve the repository root")]
+    GitRepositoryRootReadFailed { source: xshell::Error },
+    #[error("failed to install the mise tools")]
+    ProvisionMiseFailed { source: ProvisionerProvisionMiseError },
+    #[error("failed to install the toolchain with {provisioner}")]
+    InstallFailed { source: xshell::Error, provisioner: Provisioner },
+}
+
+#[derive(Error, Debug)]
+pub enum ProvisionerProvisionMiseError {
+    #[error("failed to list mise config files")]
+    MiseConfigListFailed { source: xshell::Error },
+    #[error("failed to deserialize the mise config file list")]
+    FromStrFailed { source: serde_json::Error, json: String },
+    #[error("mise returned a config file entry without a string path")]
+    MiseConfigListInvalid { configs: Vec<Value> },
+    #[error("failed to install mise tools and hooks before committing the merge")]
+    MiseInstallFailed { source: xshell::Error },
+}
@@ -0,0 +1,15 @@
+use clap::ValueEnum;
+use strum::Display;
+
+#[derive(ValueEnum, Display, Ord, PartialOrd, Eq, PartialEq, Default, Hash, Clone, Copy, Debug)]
+#[value(rename_all = "kebab-case")]
+#[strum(serialize_all = "kebab-case")]
+pub enum RebaseBranchesMode {
+    /// Rebase the branch onto the local branch
+    #[default]
+    Rebase,
+    /// Merge the local branch into the branch
+    Merge,
+}
+
+impl RebaseBranchesMode {}
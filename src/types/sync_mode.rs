@@ -0,0 +1,15 @@
+use clap::ValueEnum;
+use strum::Display;
+
+#[derive(ValueEnum, Display, Ord, PartialOrd, Eq, PartialEq, Default, Hash, Clone, Copy, Debug)]
+#[value(rename_all = "kebab-case")]
+#[strum(serialize_all = "kebab-case")]
+pub enum SyncMode {
+    /// Merge the template history into the child repository
+    #[default]
+    Merge,
+    /// Copy the managed files from the template revision and commit them as a normal commit (doesn't require shared history)
+    Files,
+}
+
+impl SyncMode {}
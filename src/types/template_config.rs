@@ -0,0 +1,117 @@
+use crate::{GitConfigGetAll, GitConfigGetAllError, SyncMode, template_config_key};
+use clap::ValueEnum;
+use errgonomic::{handle, handle_opt};
+use std::num::ParseIntError;
+use thiserror::Error;
+use xshell::{Shell, cmd};
+
+/// Per-template settings stored in the git config of the child repository under `repoconf.<remote>.*`
+#[derive(Default, Eq, PartialEq, Hash, Clone, Debug)]
+pub struct TemplateConfig {
+    /// Subdirectory of the child repository that the template is merged into (the repository root if `None`)
+    pub prefix: Option<String>,
+    /// Branch of the template remote to merge (resolved via the remote branch strategy if `None`)
+    pub branch: Option<String>,
+    /// Subdirectory of the template repository whose history is merged (the whole template repository if `None`)
+    pub path: Option<String>,
+    /// Globs of the template paths that are merged (all paths if empty); relative to the `prefix`
+    pub include: Vec<String>,
+    /// Globs of the template paths that are never merged (the child repository keeps its own version of these paths); relative to the `prefix`
+    pub exclude: Vec<String>,
+    /// How the template is synced into the child repository
+    pub sync_mode: SyncMode,
+    /// Globs of the template paths that are copied in the [`SyncMode::Files`] mode; relative to the template root
+    pub managed: Vec<String>,
+    /// Template remotes that this template is built from (e.g. "repoconf-rust-lib" extends "repoconf-base"); these templates are merged first and this template takes precedence over them
+    pub extends: Vec<String>,
+    /// Precedence of this template over the other templates of the child repository at the same depth of the inheritance chain (a higher priority is merged later and wins the conflicts between templates)
+    ///
+    /// The depth takes precedence over the priority: a template is always merged after the templates it extends and after the templates with fewer ancestors (see [`MergeCommand::order_remotes`](crate::MergeCommand::order_remotes))
+    pub priority: i64,
+}
+
+impl TemplateConfig {
+    pub fn load(sh_dir: &Shell, remote: &str) -> Result<Self, TemplateConfigLoadError> {
+        use TemplateConfigLoadError::*;
+        let prefix = Self::load_value(sh_dir, remote, "prefix")?.map(|prefix| prefix.trim_end_matches('/').to_string());
+        let branch = Self::load_value(sh_dir, remote, "branch")?;
+        let path = Self::load_value(sh_dir, remote, "path")?.map(|path| path.trim_matches('/').to_string());
+        let include = Self::load_values(sh_dir, remote, "include")?;
+        let exclude = Self::load_values(sh_dir, remote, "exclude")?;
+        let sync_mode = match Self::load_value(sh_dir, remote, "sync-mode")? {
+            Some(sync_mode) => handle_opt!(SyncMode::from_str(&sync_mode, true).ok(), SyncModeInvalid, sync_mode, remote),
+            None => SyncMode::default(),
+        };
+        let managed = Self::load_values(sh_dir, remote, "managed")?;
+        let extends = Self::load_values(sh_dir, remote, "extends")?
+            .into_iter()
+            .map(|extended| if extended.starts_with("repoconf-") { extended } else { format!("repoconf-{extended}") })
+            .collect();
+        let priority = match Self::load_value(sh_dir, remote, "priority")? {
+            Some(priority) => handle!(priority.parse::<i64>(), PriorityParseFailed, priority, remote),
+            None => 0,
+        };
+        Ok(Self {
+            prefix,
+            branch,
+            path,
+            include,
+            exclude,
+            sync_mode,
+            managed,
+            extends,
+            priority,
+        })
+    }
+
+    fn load_value(sh_dir: &Shell, remote: &str, name: &str) -> Result<Option<String>, TemplateConfigLoadError> {
+        Ok(Self::load_values(sh_dir, remote, name)?.pop())
+    }
+
+    fn load_values(sh_dir: &Shell, remote: &str, name: &str) -> Result<Vec<String>, TemplateConfigLoadError> {
+        use TemplateConfigLoadError::*;
+        let key = template_config_key(remote, name);
+        let values = handle!(sh_dir.git_config_get_all(&key), GitConfigGetAllFailed, key);
+        Ok(values)
+    }
+
+    /// Writes the single-valued settings to the git config (the settings that are `None` are left untouched; the `include`, `exclude`, `managed` and `extends` lists, the `sync-mode` and the `priority` are managed with `git config`)
+    pub fn save(&self, sh_dir: &Shell, remote: &str) -> Result<(), TemplateConfigSaveError> {
+        use TemplateConfigSaveError::*;
+        let Self {
+            prefix,
+            branch,
+            path,
+            include: _,
+            exclude: _,
+            sync_mode: _,
+            managed: _,
+            extends: _,
+            priority: _,
+        } = self;
+        [("prefix", prefix), ("branch", branch), ("path", path)]
+            .into_iter()
+            .filter_map(|(name, value)| value.as_ref().map(|value| (name, value)))
+            .try_for_each(|(name, value)| {
+                let key = template_config_key(remote, name);
+                handle!(cmd!(sh_dir, "git config {key} {value}").run_echo(), GitConfigSetFailed, key, value);
+                Ok(())
+            })
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum TemplateConfigLoadError {
+    #[error("failed to read git config key '{key}'")]
+    GitConfigGetAllFailed { source: GitConfigGetAllError, key: String },
+    #[error("template '{remote}' has an invalid sync mode '{sync_mode}'")]
+    SyncModeInvalid { sync_mode: String, remote: String },
+    #[error("failed to parse priority '{priority}' of template '{remote}'")]
+    PriorityParseFailed { source: ParseIntError, priority: String, remote: String },
+}
+
+#[derive(Error, Debug)]
+pub enum TemplateConfigSaveError {
+    #[error("failed to set git config key '{key}' to '{value}'")]
+    GitConfigSetFailed { source: xshell::Error, key: String, value: String },
+}
@@ -0,0 +1,9 @@
+/// A template remote with its position in the template inheritance chain
+#[derive(Eq, PartialEq, Hash, Clone, Debug)]
+pub struct TemplateLayer {
+    pub remote: String,
+    /// Template remotes that this template extends (directly or transitively)
+    pub ancestors: Vec<String>,
+    /// Priority of the template among the templates with the same number of ancestors (a higher priority is merged later and takes precedence)
+    pub priority: i64,
+}
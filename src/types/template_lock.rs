@@ -0,0 +1,89 @@
+use errgonomic::handle;
+use thiserror::Error;
+use xshell::{Shell, cmd};
+
+/// The template lock of the child repository: the template commits picked by [`PickCommand`](crate::PickCommand) that haven't been merged in full yet
+///
+/// The lock is a tracked git config file with a section per template remote, so it is shared with every clone of the child repository:
+///
+/// ```ini
+/// [template "repoconf-rust-lib"]
+///     picked = 0123456789abcdef0123456789abcdef01234567
+/// ```
+#[derive(Default, Eq, PartialEq, Hash, Clone, Copy, Debug)]
+pub struct TemplateLock;
+
+impl TemplateLock {
+    /// Path of the lock file relative to the root of the child repository (the `.repoconf/local` directory is owned by the child repository)
+    pub const PATH: &'static str = ".repoconf/local/lock";
+
+    /// Returns the commits picked from `remote`
+    pub fn picked(sh_dir: &Shell, remote: &str) -> Result<Vec<String>, TemplateLockPickedError> {
+        use TemplateLockPickedError::*;
+        let path = Self::PATH;
+        let key = Self::picked_key(remote);
+        // `git config --get-all` exits with 1 if the key (or the whole file) doesn't exist
+        let picked = handle!(
+            cmd!(sh_dir, "git config --file {path} --get-all {key}")
+                .ignore_status()
+                .read(),
+            GitConfigGetAllFailed,
+            key
+        );
+        Ok(picked.lines().map(ToOwned::to_owned).collect())
+    }
+
+    /// Adds the `commits` to the commits picked from `remote` and stages the lock file
+    pub fn add_picked(sh_dir: &Shell, remote: &str, commits: &[String]) -> Result<(), TemplateLockAddPickedError> {
+        use TemplateLockAddPickedError::*;
+        let path = Self::PATH;
+        let key = Self::picked_key(remote);
+        handle!(sh_dir.create_dir(".repoconf/local"), CreateDirFailed);
+        commits.iter().try_for_each(|commit| {
+            handle!(cmd!(sh_dir, "git config --file {path} --add {key} {commit}").run_echo(), GitConfigAddFailed, key: key.as_str(), commit: commit.as_str());
+            Ok(())
+        })?;
+        handle!(cmd!(sh_dir, "git add -- {path}").run_echo(), GitAddFailed);
+        Ok(())
+    }
+
+    /// Removes the `commit` from the commits picked from `remote` and stages the lock file
+    ///
+    /// PRUNING: Removes the lock entry of a picked commit, because the caller has merged the template history that contains it.
+    pub fn remove_picked(sh_dir: &Shell, remote: &str, commit: &str) -> Result<(), TemplateLockRemovePickedError> {
+        use TemplateLockRemovePickedError::*;
+        let path = Self::PATH;
+        let key = Self::picked_key(remote);
+        handle!(cmd!(sh_dir, "git config --file {path} --unset --fixed-value {key} {commit}").run_echo(), GitConfigUnsetFailed, key, commit);
+        handle!(cmd!(sh_dir, "git add -- {path}").run_echo(), GitAddFailed);
+        Ok(())
+    }
+
+    fn picked_key(remote: &str) -> String {
+        format!("template.{remote}.picked")
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum TemplateLockPickedError {
+    #[error("failed to read '{key}' from the template lock")]
+    GitConfigGetAllFailed { source: xshell::Error, key: String },
+}
+
+#[derive(Error, Debug)]
+pub enum TemplateLockAddPickedError {
+    #[error("failed to create the directory of the template lock")]
+    CreateDirFailed { source: xshell::Error },
+    #[error("failed to add '{commit}' to '{key}' in the template lock")]
+    GitConfigAddFailed { source: xshell::Error, key: String, commit: String },
+    #[error("failed to stage the template lock")]
+    GitAddFailed { source: xshell::Error },
+}
+
+#[derive(Error, Debug)]
+pub enum TemplateLockRemovePickedError {
+    #[error("failed to remove '{commit}' from '{key}' in the template lock")]
+    GitConfigUnsetFailed { source: xshell::Error, key: String, commit: String },
+    #[error("failed to stage the template lock")]
+    GitAddFailed { source: xshell::Error },
+}
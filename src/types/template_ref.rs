@@ -0,0 +1,130 @@
+use crate::RepoName;
+use errgonomic::handle;
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+use thiserror::Error;
+use url::Url;
+
+/// A reference to a template: `<url>`, `<url>#<branch>` or `<url>#<branch>:<path>`
+///
+/// The `path` points to a subdirectory of the template repository (e.g. `templates/rust-lib` in a monorepo of templates). Only the history of this subdirectory is merged into the child repository.
+#[derive(Eq, PartialEq, Hash, Clone, Debug)]
+pub struct TemplateRef {
+    /// Template repo URL without the fragment
+    pub url: Url,
+    /// Branch of the template repo (the branch is resolved automatically if `None`)
+    pub branch: Option<String>,
+    /// Subdirectory of the template repo (the repository root if `None`)
+    pub path: Option<String>,
+}
+
+impl TemplateRef {
+    /// Returns the name of the template: the last component of the `path` if it is set, otherwise the name of the template repo
+    pub fn name(&self) -> &str {
+        match &self.path {
+            Some(path) => path.rsplit('/').next().unwrap_or(path),
+            None => self.url.repo_name(),
+        }
+    }
+}
+
+impl Display for TemplateRef {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let url = &self.url;
+        match (&self.branch, &self.path) {
+            (None, None) => write!(f, "{url}"),
+            (Some(branch), None) => write!(f, "{url}#{branch}"),
+            (branch, Some(path)) => write!(f, "{url}#{branch}:{path}", branch = branch.as_deref().unwrap_or_default()),
+        }
+    }
+}
+
+impl From<Url> for TemplateRef {
+    fn from(mut url: Url) -> Self {
+        let fragment = url.fragment().map(ToString::to_string);
+        url.set_fragment(None);
+        let (branch, path) = match fragment {
+            None => (None, None),
+            Some(fragment) => match fragment.split_once(':') {
+                None => (Some(fragment), None),
+                Some((branch, path)) => (Some(branch.to_string()), Some(path.trim_matches('/').to_string())),
+            },
+        };
+        Self {
+            url,
+            branch: branch.filter(|branch| !branch.is_empty()),
+            path: path.filter(|path| !path.is_empty()),
+        }
+    }
+}
+
+impl FromStr for TemplateRef {
+    type Err = TemplateRefFromStrError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        use TemplateRefFromStrError::*;
+        let url = handle!(Url::parse(s), UrlParseFailed, input: s);
+        Ok(Self::from(url))
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum TemplateRefFromStrError {
+    #[error("failed to parse template URL '{input}'")]
+    UrlParseFailed { source: url::ParseError, input: String },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const URL: &str = "https://github.com/example/repoconf-templates";
+
+    #[test]
+    fn must_parse_a_url_without_fragment() {
+        let template_ref = TemplateRef::from_str(URL).unwrap();
+        assert_eq!(template_ref.url.as_str(), URL);
+        assert_eq!(template_ref.branch, None);
+        assert_eq!(template_ref.path, None);
+        assert_eq!(template_ref.name(), "repoconf-templates");
+    }
+
+    #[test]
+    fn must_parse_a_url_with_branch_and_path() {
+        let template_ref = TemplateRef::from_str(&format!("{URL}#main:templates/rust-lib/")).unwrap();
+        assert_eq!(template_ref.url.as_str(), URL);
+        assert_eq!(template_ref.branch.as_deref(), Some("main"));
+        assert_eq!(template_ref.path.as_deref(), Some("templates/rust-lib"));
+        assert_eq!(template_ref.name(), "rust-lib");
+        assert_eq!(template_ref.to_string(), format!("{URL}#main:templates/rust-lib"));
+    }
+
+    #[test]
+    fn must_parse_a_url_with_branch_only() {
+        let template_ref = TemplateRef::from_str(&format!("{URL}#develop")).unwrap();
+        assert_eq!(template_ref.branch.as_deref(), Some("develop"));
+        assert_eq!(template_ref.path, None);
+    }
+
+    #[test]
+    fn must_parse_a_url_with_path_only() {
+        let template_ref = TemplateRef::from_str(&format!("{URL}#:templates/rust-lib")).unwrap();
+        assert_eq!(template_ref.branch, None);
+        assert_eq!(template_ref.path.as_deref(), Some("templates/rust-lib"));
+        assert_eq!(template_ref.to_string(), format!("{URL}#:templates/rust-lib"));
+    }
+
+    #[test]
+    fn must_ignore_an_empty_fragment() {
+        let template_ref = TemplateRef::from_str(&format!("{URL}#")).unwrap();
+        assert_eq!(template_ref.url.as_str(), URL);
+        assert_eq!(template_ref.branch, None);
+        assert_eq!(template_ref.path, None);
+    }
+
+    #[test]
+    fn must_reject_an_invalid_url() {
+        let error = TemplateRef::from_str("not a url#main:templates").unwrap_err();
+        assert!(matches!(error, TemplateRefFromStrError::UrlParseFailed { .. }));
+    }
+}
@@ -0,0 +1,99 @@
+use errgonomic::{ErrVec, handle, handle_iter};
+use itertools::Itertools;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+use xshell::{Shell, cmd};
+
+/// A variable declared by a template in its `.repoconf/variables` manifest
+///
+/// The manifest is a git config file with a section per variable:
+///
+/// ```ini
+/// [variable "name"]
+///     description = Name of the project
+///     default = my-project
+/// ```
+///
+/// The `{{name}}` placeholders in the contents and the names of the template files are replaced with the value of the variable during `init`.
+#[derive(Default, Eq, PartialEq, Hash, Clone, Debug)]
+pub struct TemplateVariable {
+    pub name: String,
+    /// Description shown in the interactive prompt
+    pub description: Option<String>,
+    /// Value used if no value is provided via a flag, the environment or the prompt
+    pub default: Option<String>,
+}
+
+impl TemplateVariable {
+    pub const MANIFEST_PATH: &'static str = ".repoconf/variables";
+
+    /// Loads all variables declared in the `manifest` in the order of declaration
+    pub fn load_all(sh: &Shell, manifest: &Path) -> Result<Vec<Self>, TemplateVariableLoadAllError> {
+        use TemplateVariableLoadAllError::*;
+        let keys = handle!(cmd!(sh, "git config --file {manifest} --name-only --list").read(), GitConfigListFailed, manifest);
+        let names = keys
+            .lines()
+            .filter_map(|key| key.strip_prefix("variable."))
+            .filter_map(|key| key.rsplit_once('.'))
+            .map(|(name, _)| name.to_string())
+            .unique()
+            .collect_vec();
+        let variables = handle_iter!(names.into_iter().map(|name| Self::load(sh, manifest, name)), LoadFailed, manifest);
+        Ok(variables)
+    }
+
+    fn load(sh: &Shell, manifest: &Path, name: String) -> Result<Self, TemplateVariableLoadError> {
+        use TemplateVariableLoadError::*;
+        let description_key = format!("variable.{name}.description");
+        let default_key = format!("variable.{name}.default");
+        // `git config --get` exits with 1 if the key is not set
+        let description = handle!(cmd!(sh, "git config --file {manifest} --get {description_key}").ignore_status().read(), GitConfigGetFailed, key: description_key);
+        let default = handle!(cmd!(sh, "git config --file {manifest} --get {default_key}").ignore_status().read(), GitConfigGetFailed, key: default_key);
+        Ok(Self {
+            name,
+            description: Some(description).filter(|description| !description.is_empty()),
+            default: Some(default).filter(|default| !default.is_empty()),
+        })
+    }
+
+    /// Returns the environment variable that provides the value of the variable `name` (e.g. `REPOCONF_VAR_PROJECT_NAME` for `project-name`)
+    pub fn env_name(name: &str) -> String {
+        format!("REPOCONF_VAR_{}", name.to_uppercase().replace('-', "_"))
+    }
+
+    /// Returns the placeholder of the variable `name` (e.g. `{{name}}`)
+    pub fn placeholder(name: &str) -> String {
+        format!("{{{{{name}}}}}")
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum TemplateVariableLoadAllError {
+    #[error("failed to list the keys of the variables manifest '{manifest}'")]
+    GitConfigListFailed { source: xshell::Error, manifest: PathBuf },
+    #[error("failed to load {len} variables from the manifest '{manifest}'", len = source.len())]
+    LoadFailed { source: ErrVec<TemplateVariableLoadError>, manifest: PathBuf },
+}
+
+#[derive(Error, Debug)]
+pub enum TemplateVariableLoadError {
+    #[error("failed to read the variables manifest key '{key}'")]
+    GitConfigGetFailed { source: xshell::Error, key: String },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn must_wrap_the_name_in_braces() {
+        assert_eq!(TemplateVariable::placeholder("project-name"), "{{project-name}}");
+    }
+
+    #[test]
+    fn must_convert_the_name_to_an_env_name() {
+        assert_eq!(TemplateVariable::env_name("project"), "REPOCONF_VAR_PROJECT");
+        assert_eq!(TemplateVariable::env_name("project-name"), "REPOCONF_VAR_PROJECT_NAME");
+        assert_eq!(TemplateVariable::env_name("Project_Name"), "REPOCONF_VAR_PROJECT_NAME");
+    }
+}
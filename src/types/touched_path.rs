@@ -0,0 +1,6 @@
+/// A path that has been modified by a template merge in the current run
+#[derive(Eq, PartialEq, Hash, Clone, Debug)]
+pub struct TouchedPath {
+    pub path: String,
+    pub remote: String,
+}
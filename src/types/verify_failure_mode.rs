@@ -0,0 +1,15 @@
+use clap::ValueEnum;
+use strum::Display;
+
+#[derive(ValueEnum, Display, Ord, PartialOrd, Eq, PartialEq, Default, Hash, Clone, Copy, Debug)]
+#[value(rename_all = "kebab-case")]
+#[strum(serialize_all = "kebab-case")]
+pub enum VerifyFailureMode {
+    /// Reset the local branch to the pre-merge commit (the merge remains reachable via the reflog)
+    #[default]
+    Reset,
+    /// Keep the merge on the `repoconf/unverified/<local-branch>` branch and reset the local branch to the pre-merge commit
+    Branch,
+}
+
+impl VerifyFailureMode {}